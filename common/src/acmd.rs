@@ -0,0 +1,451 @@
+//! Finds the bones with collision in locally dumped fighter scripts (ACMD).
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// The calls that attach a hitbox, grab box, search box or hurtbox to a bone, which is their first `Hash40` argument.
+const COLLISION_CALLS: [&str; 5] = ["ATTACK", "CATCH", "SEARCH", "HIT_NODE", "ATTACK_LINE"];
+
+/// The bones with collision in each motion, by fighter.
+///
+/// Bone names are lowercase like in the scripts, so compare them with anim and skeleton names ignoring case.
+#[derive(Debug, Default)]
+pub struct HitboxBones {
+    fighters: BTreeMap<String, BTreeMap<String, BTreeSet<String>>>,
+}
+
+impl HitboxBones {
+    /// Reads a dumped script file, or every file in a folder and its subfolders.
+    ///
+    /// The fighter is the first folder inside `path`, like `mario` for `dump/mario/game.rs`, or the file name without
+    /// its extension for files directly inside it.
+    pub fn from_dump(path: &Path) -> Result<Self> {
+        let mut hitbox_bones = Self::default();
+        if path.is_file() {
+            let fighter = path.file_stem().unwrap_or_default().to_string_lossy();
+            hitbox_bones.read_script_file(&fighter, path)?;
+        } else {
+            hitbox_bones.read_script_dir(path, None)?;
+        }
+        Ok(hitbox_bones)
+    }
+
+    fn read_script_dir(&mut self, dir: &Path, fighter: Option<&str>) -> Result<()> {
+        let entries = fs::read_dir(dir)
+            .with_context(|| format!("could not read the script folder `{}`", dir.display()))?;
+        for entry in entries {
+            let path = entry
+                .with_context(|| format!("could not read the script folder `{}`", dir.display()))?
+                .path();
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            let fighter = fighter.unwrap_or(&name);
+            if path.is_dir() {
+                self.read_script_dir(&path, Some(fighter))?;
+            } else {
+                self.read_script_file(fighter, &path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_script_file(&mut self, fighter: &str, path: &Path) -> Result<()> {
+        let bytes = fs::read(path)
+            .with_context(|| format!("could not read the script `{}`", path.display()))?;
+        self.parse_scripts(fighter, &String::from_utf8_lossy(&bytes));
+        Ok(())
+    }
+
+    /// Adds the collision bones of every script function in `text`, ignoring comments and string literals.
+    ///
+    /// A motion is named after the function it's in, without the `game_` prefix, like `attack11` for `game_attack11`.
+    /// Every `game_` function is recorded, so a motion without collision is known to have no collision bones.
+    pub fn parse_scripts(&mut self, fighter: &str, text: &str) {
+        let (code, structure) = blank_comments_and_literals(text);
+        let mut motion = None;
+        let mut position = 0;
+        while position < structure.len() {
+            let rest = &structure[position..];
+            let Some(identifier_start) = rest.find(is_identifier_char) else {
+                break;
+            };
+            let identifier_length = rest[identifier_start..]
+                .find(|c: char| !is_identifier_char(c))
+                .unwrap_or(rest.len() - identifier_start);
+            let identifier = &rest[identifier_start..identifier_start + identifier_length];
+            let after_identifier = &rest[identifier_start + identifier_length..];
+            position += identifier_start + identifier_length;
+
+            if identifier == "fn" {
+                let name = after_identifier.trim_start();
+                let name_length = name
+                    .find(|c: char| !is_identifier_char(c))
+                    .unwrap_or(name.len());
+                let name = &name[..name_length];
+                let motion_name = name.strip_prefix("game_").unwrap_or(name).to_lowercase();
+                if name.starts_with("game_") {
+                    self.fighters
+                        .entry(fighter.to_string())
+                        .or_default()
+                        .entry(motion_name.clone())
+                        .or_default();
+                }
+                motion = Some(motion_name);
+            } else if COLLISION_CALLS.contains(&identifier) && after_identifier.starts_with('(') {
+                let arguments_start = position;
+                let arguments = call_arguments(after_identifier);
+                let arguments =
+                    &code[arguments_start + arguments.start..arguments_start + arguments.end];
+                if let (Some(motion), Some(bone)) = (&motion, first_hash40(arguments)) {
+                    self.fighters
+                        .entry(fighter.to_string())
+                        .or_default()
+                        .entry(motion.clone())
+                        .or_default()
+                        .insert(bone.to_lowercase());
+                }
+            }
+        }
+    }
+
+    /// The collision bones of the motion of an anim, by the anim's file name like `c02attack11.nuanmb` for `attack11`.
+    /// Every collision bone of the fighter is returned if the scripts have no function for the motion.
+    ///
+    /// The fighter is `fighter` if given, else the only fighter in the scripts, else the folder after `fighter` in any
+    /// of `anim_paths`, like `mario` for `fighter/mario/motion/body/c00/c02attack11.nuanmb`. The motion comes from the
+    /// first path.
+    pub fn bones_for_anim(
+        &self,
+        fighter: Option<&str>,
+        anim_paths: &[&Path],
+    ) -> Result<BTreeSet<&str>> {
+        let fighter = match fighter {
+            Some(fighter) => fighter.to_string(),
+            None if self.fighters.len() == 1 => self.fighters.keys().next().unwrap().clone(),
+            None => anim_paths
+                .iter()
+                .find_map(|path| fighter_from_path(path))
+                .with_context(|| {
+                    format!(
+                        "can't tell which fighter's scripts {:?} uses, since the scripts have several fighters. Use --fighter to choose one",
+                        anim_paths.first().and_then(|path| path.file_name()).unwrap_or_default()
+                    )
+                })?,
+        };
+        let motions = self
+            .fighters
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&fighter))
+            .map(|(_, motions)| motions)
+            .ok_or_else(|| anyhow::format_err!("the scripts have no fighter `{fighter}`"))?;
+
+        let motion_bones = anim_paths
+            .first()
+            .and_then(|path| motion_from_path(path))
+            .and_then(|motion| motions.get(&motion));
+        Ok(match motion_bones {
+            Some(bones) => bones.iter().map(String::as_str).collect(),
+            None => motions.values().flatten().map(String::as_str).collect(),
+        })
+    }
+
+    /// Every bone with collision in any motion of any fighter.
+    pub fn all_bones(&self) -> BTreeSet<&str> {
+        self.fighters
+            .values()
+            .flat_map(|motions| motions.values())
+            .flatten()
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Prints the bones with collision of every motion, and of each fighter as a whole.
+    pub fn print_summary(&self) {
+        for (fighter, motions) in &self.fighters {
+            let motions = motions
+                .iter()
+                .filter(|(_, bones)| !bones.is_empty())
+                .collect::<BTreeMap<_, _>>();
+            for (motion, bones) in &motions {
+                println!(
+                    "HITBOX BONES: fighter=`{fighter}`, motion=`{motion}`, bones=`{}`",
+                    bones
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            let fighter_bones = motions
+                .values()
+                .flat_map(|bones| bones.iter())
+                .collect::<BTreeSet<_>>();
+            println!(
+                "Found {} bones with collision in {} motions of `{fighter}`.",
+                fighter_bones.len(),
+                motions.len()
+            );
+        }
+    }
+}
+
+/// The fighter of an anim from its path, like `mario` for `fighter/mario/motion/body/c00/a00wait1.nuanmb`.
+fn fighter_from_path(path: &Path) -> Option<String> {
+    let mut components = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_lowercase());
+    components.find(|component| component == "fighter")?;
+    components.next()
+}
+
+/// The motion of an anim from its file name without the slot prefix, like `attack11` for `c02attack11.nuanmb`.
+fn motion_from_path(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?.to_lowercase();
+    let prefix = stem.as_bytes().get(..3)?;
+    let has_slot_prefix =
+        prefix[0].is_ascii_lowercase() && prefix[1].is_ascii_digit() && prefix[2].is_ascii_digit();
+    (has_slot_prefix && stem.len() > 3).then(|| stem[3..].to_string())
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+enum ScanState {
+    Code,
+    LineComment,
+    BlockComment,
+    /// Inside a string or char literal that ends with this quote.
+    Literal(char),
+}
+
+/// Copies of `text` with its comments replaced by spaces, and with the contents of its string and char literals also
+/// replaced by spaces, so neither matches script calls that were commented out or only mentioned in a string.
+///
+/// Every character is replaced by as many spaces as it has bytes, so a position is the same in `text` and both copies.
+fn blank_comments_and_literals(text: &str) -> (String, String) {
+    let mut code = String::with_capacity(text.len());
+    let mut structure = String::with_capacity(text.len());
+    let mut push = |c: char, keep_in_code: bool, keep_in_structure: bool| {
+        for (copy, keep) in [
+            (&mut code, keep_in_code),
+            (&mut structure, keep_in_structure),
+        ] {
+            if keep {
+                copy.push(c);
+            } else {
+                copy.push_str(&" ".repeat(c.len_utf8()));
+            }
+        }
+    };
+
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut state = ScanState::Code;
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        let next = chars.get(index + 1).copied();
+        match state {
+            ScanState::Code => match (c, next) {
+                ('/', Some('/')) => {
+                    state = ScanState::LineComment;
+                    push(c, false, false);
+                }
+                ('/', Some('*')) => {
+                    state = ScanState::BlockComment;
+                    push(c, false, false);
+                    push('*', false, false);
+                    index += 1;
+                }
+                ('"', _) => {
+                    state = ScanState::Literal('"');
+                    push(c, true, true);
+                }
+                // A char literal like `'"'` or `'\''`, but not a lifetime like `'a`.
+                ('\'', Some(_)) if next == Some('\\') || chars.get(index + 2) == Some(&'\'') => {
+                    state = ScanState::Literal('\'');
+                    push(c, true, true);
+                }
+                _ => push(c, true, true),
+            },
+            ScanState::LineComment => {
+                if c == '\n' {
+                    state = ScanState::Code;
+                    push(c, true, true);
+                } else {
+                    push(c, false, false);
+                }
+            }
+            ScanState::BlockComment => {
+                push(c, false, false);
+                if c == '*' && next == Some('/') {
+                    state = ScanState::Code;
+                    push('/', false, false);
+                    index += 1;
+                }
+            }
+            ScanState::Literal(quote) => {
+                if c == '\\' {
+                    push(c, true, false);
+                    if let Some(escaped) = next {
+                        push(escaped, true, false);
+                        index += 1;
+                    }
+                } else if c == quote {
+                    state = ScanState::Code;
+                    push(c, true, true);
+                } else {
+                    push(c, true, false);
+                }
+            }
+        }
+        index += 1;
+    }
+    (code, structure)
+}
+
+/// The range of the text between the opening parenthesis at the start of `text` and its closing parenthesis.
+fn call_arguments(text: &str) -> Range<usize> {
+    let mut depth = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return 1..index;
+                }
+            }
+            _ => (),
+        }
+    }
+    1.min(text.len())..text.len()
+}
+
+/// The name in the first `Hash40::new("...")`, or `hash40("...")` in older dumps.
+fn first_hash40(arguments: &str) -> Option<&str> {
+    let start = ["Hash40::new(\"", "hash40(\""]
+        .iter()
+        .filter_map(|prefix| Some(arguments.find(prefix)? + prefix.len()))
+        .min()?;
+    let length = arguments[start..].find('"')?;
+    Some(&arguments[start..start + length])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = r#"
+unsafe extern "C" fn game_attack11(agent: &mut L2CAgentBase) {
+    frame(agent.lua_state_agent, 3.0);
+    if macros::is_excute(agent) {
+        macros::ATTACK(agent, 0, 0, Hash40::new("top"), 2.2, 361, 25, 0, 20, 2.0, 0.0, 9.0, 5.5, None, None, None, 1.6, 1.0, *ATTACK_SETOFF_KIND_ON, *ATTACK_LR_CHECK_POS, false, 0, 0.0, 0, false, false, false, false, true, *COLLISION_SITUATION_MASK_GA, *COLLISION_CATEGORY_MASK_ALL, *COLLISION_PART_MASK_ALL, false, Hash40::new("collision_attr_normal"), *ATTACK_SOUND_LEVEL_S, *COLLISION_SOUND_ATTR_PUNCH, *ATTACK_REGION_PUNCH);
+        macros::ATTACK(agent, 1, 0,
+            Hash40::new("armr"), 2.2, 361, 25, 0, 20, 2.0, 0.0, 0.0, 0.0, None, None, None, 1.6, 1.0, *ATTACK_SETOFF_KIND_ON, *ATTACK_LR_CHECK_POS, false, 0, 0.0, 0, false, false, false, false, true, *COLLISION_SITUATION_MASK_GA, *COLLISION_CATEGORY_MASK_ALL, *COLLISION_PART_MASK_ALL, false, Hash40::new("collision_attr_normal"), *ATTACK_SOUND_LEVEL_S, *COLLISION_SOUND_ATTR_PUNCH, *ATTACK_REGION_PUNCH);
+        macros::HIT_NODE(agent, Hash40::new("HandR"), *HIT_STATUS_XLU);
+    }
+}
+
+unsafe extern "C" fn effect_attack11(agent: &mut L2CAgentBase) {
+    macros::EFFECT(agent, Hash40::new("sys_attack_arc"), Hash40::new("handl"), 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, true);
+}
+
+unsafe extern "C" fn game_catch(agent: &mut L2CAgentBase) {
+    macros::CATCH(agent, 0, Hash40::new("top"), 3.3, 0.0, 8.0, 4.0, Some(0.0), Some(8.0), Some(8.7), *FIGHTER_STATUS_KIND_CAPTURE_PULLED, *COLLISION_SITUATION_MASK_GA);
+    macros::ATTACK_ABS(agent, *FIGHTER_ATTACK_ABSOLUTE_KIND_CATCH, 0, 3.0, 0, 100, 0, 60, 0.0, 1.0, *ATTACK_LR_CHECK_F, 0.0, true, Hash40::new("collision_attr_normal"), *ATTACK_SOUND_LEVEL_S, *COLLISION_SOUND_ATTR_NONE, *ATTACK_REGION_THROW);
+}
+"#;
+
+    #[test]
+    fn collision_bones_by_motion() {
+        let mut hitbox_bones = HitboxBones::default();
+        hitbox_bones.parse_scripts("mario", SCRIPT);
+
+        let motions = &hitbox_bones.fighters["mario"];
+        assert_eq!(
+            motions.keys().collect::<Vec<_>>(),
+            vec!["attack11", "catch"]
+        );
+        assert_eq!(
+            motions["attack11"].iter().collect::<Vec<_>>(),
+            vec!["armr", "handr", "top"]
+        );
+        assert_eq!(
+            hitbox_bones.all_bones().into_iter().collect::<Vec<_>>(),
+            vec!["armr", "handr", "top"]
+        );
+    }
+
+    #[test]
+    fn older_hash40_syntax() {
+        let mut hitbox_bones = HitboxBones::default();
+        hitbox_bones.parse_scripts(
+            "luigi",
+            "fn game_attacks3() { ATTACK(0, 0, hash40(\"legr\"), 9.0) }",
+        );
+        assert_eq!(
+            hitbox_bones.all_bones().into_iter().collect::<Vec<_>>(),
+            vec!["legr"]
+        );
+    }
+
+    #[test]
+    fn comments_and_strings_are_ignored() {
+        let mut hitbox_bones = HitboxBones::default();
+        hitbox_bones.parse_scripts(
+            "mario",
+            r#"
+fn game_attack11(agent: &mut L2CAgentBase) {
+    // macros::ATTACK(agent, 0, 0, Hash40::new("legl"), 2.2);
+    /* macros::ATTACK(agent, 1, 0, Hash40::new("legr"), 2.2); */
+    let note = "ATTACK(agent, 2, 0, Hash40::new("kneel")) // not code";
+    let quote = '"';
+    macros::ATTACK(agent, 3, 0, /* Hash40::new("kneer"), */ Hash40::new("top"), 2.2);
+}
+"#,
+        );
+        assert_eq!(
+            hitbox_bones.all_bones().into_iter().collect::<Vec<_>>(),
+            vec!["top"]
+        );
+    }
+
+    #[test]
+    fn bones_of_the_anims_motion_and_fighter() {
+        let mut hitbox_bones = HitboxBones::default();
+        hitbox_bones.parse_scripts("mario", SCRIPT);
+        hitbox_bones.parse_scripts("mario", "fn game_wait1(agent: &mut L2CAgentBase) {}");
+        hitbox_bones.parse_scripts(
+            "luigi",
+            "fn game_attack11() { ATTACK(0, 0, Hash40::new(\"legr\"), 9.0) }",
+        );
+        let bones = |fighter: Option<&str>, path: &str| {
+            hitbox_bones
+                .bones_for_anim(fighter, &[Path::new(path)])
+                .map(|bones| bones.into_iter().collect::<Vec<_>>())
+        };
+
+        let motion_path = "fighter/mario/motion/body/c00/c02attack11.nuanmb";
+        assert_eq!(
+            bones(None, motion_path).unwrap(),
+            vec!["armr", "handr", "top"]
+        );
+        assert_eq!(bones(Some("luigi"), motion_path).unwrap(), vec!["legr"]);
+        // A motion without collision has no collision bones.
+        assert!(bones(None, "fighter/mario/motion/body/c00/a00wait1.nuanmb")
+            .unwrap()
+            .is_empty());
+        // An anim without a script falls back to every collision bone of the fighter.
+        assert_eq!(
+            bones(None, "fighter/mario/motion/body/c00/d00appeal.nuanmb").unwrap(),
+            vec!["armr", "handr", "top"]
+        );
+
+        // The fighter can't be told from the path, and the scripts have several fighters.
+        assert!(bones(None, "c02attack11.nuanmb").is_err());
+        assert!(bones(Some("peach"), motion_path).is_err());
+    }
+}
@@ -0,0 +1,16 @@
+//! Lookups in the groups of an ssbh_lib anim, so the splicer and the validator find nodes and their data the same way.
+
+use ssbh_lib::formats::anim::{Group, Node, TrackV2};
+use ssbh_lib::{SsbhByteBuffer, SsbhString};
+
+/// The track's data in the buffer, or `None` if it isn't inside the buffer.
+pub fn track_data<'a>(track: &TrackV2, buffer: &'a SsbhByteBuffer) -> Option<&'a [u8]> {
+    let start_index = usize::try_from(track.data_offset).ok()?;
+    let end_index = start_index.checked_add(usize::try_from(track.data_size).ok()?)?;
+    buffer.elements.get(start_index..end_index)
+}
+
+/// Finds the node whose name has exactly the same bytes as `name`.
+pub fn find_node<'a>(group: &'a Group, name: &SsbhString) -> Option<&'a Node> {
+    group.nodes.elements.iter().find(|node| node.name == *name)
+}
@@ -0,0 +1,245 @@
+use anyhow::{Context, Result};
+use ssbh_lib::prelude::*;
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub fn is_zip_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// Splits a path like `mod.zip/fighter/mario/a00wait1.nuanmb` into the zip file and the entry inside it.
+///
+/// Returns `None` for a path that isn't inside a zip file.
+pub fn split_zip_path(path: &Path) -> Option<(&Path, String)> {
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| is_zip_path(ancestor) && ancestor.is_file())?;
+    let entry = path
+        .strip_prefix(archive)
+        .ok()?
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    Some((archive, entry))
+}
+
+fn open_zip(archive: &Path) -> Result<ZipArchive<File>> {
+    let file = File::open(archive)
+        .with_context(|| format!("could not open the zip file `{}`", archive.display()))?;
+    ZipArchive::new(file)
+        .with_context(|| format!("could not read the zip file `{}`", archive.display()))
+}
+
+/// Reads a file, or an entry of a zip file without extracting it.
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    let Some((archive, entry)) = split_zip_path(path) else {
+        return fs::read(path).with_context(|| format!("could not read `{}`", path.display()));
+    };
+    let mut zip = open_zip(archive)?;
+    let mut file = zip.by_name(&entry).with_context(|| {
+        format!(
+            "could not find `{entry}` in the zip file `{}`",
+            archive.display()
+        )
+    })?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)
+        .with_context(|| format!("could not read `{}`", path.display()))?;
+    Ok(bytes)
+}
+
+pub fn read_to_string(path: &Path) -> Result<String> {
+    String::from_utf8(read(path)?)
+        .with_context(|| format!("`{}` isn't valid UTF-8", path.display()))
+}
+
+/// Reads an anim like [Anim::from_file], but also from inside a zip file.
+pub fn read_anim(path: &Path) -> Result<Anim> {
+    let bytes = read(path)?;
+    Anim::read(&mut Cursor::new(bytes))
+        .with_context(|| format!("could not parse the anim `{}`", path.display()))
+}
+
+/// Encodes an anim to the bytes [Anim::write_to_file] would write.
+pub fn anim_bytes(anim: &Anim) -> Result<Vec<u8>> {
+    let mut writer = Cursor::new(Vec::new());
+    anim.write(&mut writer)
+        .context("could not encode the anim")?;
+    Ok(writer.into_inner())
+}
+
+/// Lists the entries of a zip file that `is_anim` accepts, as paths inside the zip file, at any depth.
+pub fn read_anim_zip(archive: &Path, is_anim: fn(&Path) -> bool) -> Result<Vec<PathBuf>> {
+    let mut zip = open_zip(archive)?;
+    let mut anim_paths = Vec::new();
+    for index in 0..zip.len() {
+        let file = zip
+            .by_index(index)
+            .with_context(|| format!("could not read the zip file `{}`", archive.display()))?;
+        if file.is_dir() {
+            continue;
+        }
+        // Entries like `../a00wait1.nuanmb` would point outside the zip file once written out again.
+        let Some(entry) = file.enclosed_name().map(Path::to_path_buf) else {
+            println!(
+                "SKIPPED: Skipping {:?} in {archive:?}, since its path isn't safe.",
+                file.name()
+            );
+            continue;
+        };
+        if is_anim(&entry) {
+            anim_paths.push(archive.join(entry));
+        } else {
+            println!(
+                "SKIPPED: Skipping {:?} in {archive:?}, since it's not an anim.",
+                file.name()
+            );
+        }
+    }
+    Ok(anim_paths)
+}
+
+/// The canonical form of a path, even if it doesn't exist yet, like an output file or folder.
+fn normalize(path: &Path) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(path) {
+        return path;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            normalize(parent).join(file_name)
+        }
+        _ => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// Whether the paths point at the same file or folder, following `..` and links wherever the path exists.
+pub fn is_same_path(a: &Path, b: &Path) -> bool {
+    let (a, b) = (normalize(a), normalize(b));
+    // Windows paths are case-insensitive.
+    if cfg!(windows) {
+        a.as_os_str().eq_ignore_ascii_case(b.as_os_str())
+    } else {
+        a == b
+    }
+}
+
+pub fn is_json_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+pub fn is_nuanmb_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("nuanmb"))
+}
+
+/// Lists the files in `dir` that `is_anim` accepts, reporting every other entry instead of failing on it.
+///
+/// `dir` can also be a zip file, whose anims are listed at any depth.
+pub fn read_anim_dir(dir: &Path, is_anim: fn(&Path) -> bool) -> Result<Vec<PathBuf>> {
+    if is_zip_path(dir) && dir.is_file() {
+        return read_anim_zip(dir, is_anim);
+    }
+
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("could not read the folder `{}`", dir.display()))?;
+
+    let mut anim_paths = Vec::new();
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(e) => {
+                println!(
+                    "WARNING: could not read an entry of the folder `{}`, error=`{e}`",
+                    dir.display()
+                );
+                continue;
+            }
+        };
+        if path.is_file() && is_anim(&path) {
+            anim_paths.push(path);
+        } else {
+            println!("SKIPPED: Skipping {path:?}, since it's not an anim.");
+        }
+    }
+    Ok(anim_paths)
+}
+
+/// The folders of an anim inside its zip file, like `fighter/mario/motion/body/c00`, which are empty for a plain file.
+pub fn zip_entry_dir(path: &Path) -> PathBuf {
+    split_zip_path(path)
+        .and_then(|(_, entry)| Path::new(&entry).parent().map(Path::to_path_buf))
+        .unwrap_or_default()
+}
+
+/// Writes the files to a new zip file, in order.
+pub fn write_zip(path: &Path, files: &[(String, Vec<u8>)]) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("could not create the zip file `{}`", path.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (entry, bytes) in files {
+        zip.start_file(entry, options)
+            .and_then(|_| Ok(zip.write_all(bytes)?))
+            .with_context(|| format!("could not add `{entry}` to `{}`", path.display()))?;
+    }
+    zip.finish()
+        .with_context(|| format!("could not finish the zip file `{}`", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_anims::TestDir;
+
+    fn is_nuanmb_path(path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|extension| extension == "nuanmb")
+    }
+
+    #[test]
+    fn anims_are_read_from_inside_zip() {
+        let dir = TestDir::new();
+        let zip_path = dir.path.join("mod.zip");
+        write_zip(
+            &zip_path,
+            &[
+                ("fighter/c00/a00wait1.nuanmb".to_string(), b"anim".to_vec()),
+                ("readme.txt".to_string(), b"text".to_vec()),
+            ],
+        )
+        .unwrap();
+
+        let anim_paths = read_anim_zip(&zip_path, is_nuanmb_path).unwrap();
+        assert_eq!(
+            anim_paths,
+            vec![zip_path.join("fighter/c00/a00wait1.nuanmb")]
+        );
+        assert_eq!(read(&anim_paths[0]).unwrap(), b"anim");
+        assert_eq!(zip_entry_dir(&anim_paths[0]), Path::new("fighter/c00"));
+        assert!(read(&zip_path.join("missing.nuanmb")).is_err());
+    }
+
+    #[test]
+    fn plain_files_are_not_in_a_zip() {
+        let dir = TestDir::new();
+        let path = dir.path.join("a00wait1.nuanmb");
+        fs::write(&path, b"anim").unwrap();
+
+        assert!(split_zip_path(&path).is_none());
+        assert_eq!(read(&path).unwrap(), b"anim");
+        assert_eq!(zip_entry_dir(&path), PathBuf::new());
+    }
+}
@@ -0,0 +1,17 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use crate::archive;
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Hashes a file, or an entry of a zip file.
+pub fn hash_file(path: &Path) -> Result<String> {
+    Ok(sha256_hex(&archive::read(path)?))
+}
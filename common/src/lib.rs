@@ -0,0 +1,9 @@
+//! Code shared by the splicer and the validator, so both read anims and apply their rules the same way.
+
+pub mod acmd;
+pub mod anim;
+pub mod archive;
+pub mod hash;
+#[cfg(any(test, feature = "test_anims"))]
+pub mod test_anims;
+pub mod validation;
@@ -0,0 +1,143 @@
+//! Small synthetic anims for tests, so nothing depends on a game dump.
+
+pub use ssbh_data::anim_data::AnimData;
+use ssbh_data::anim_data::{
+    GroupData, GroupType, NodeData, TrackData, TrackValues, Transform, TransformFlags,
+};
+use ssbh_data::{Vector3, Vector4};
+use ssbh_lib::prelude::*;
+use std::fs;
+
+use crate::archive;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Every synthetic anim is this many frames long.
+pub const FRAME_COUNT: usize = 3;
+
+pub fn anim_data(minor_version: u16, groups: Vec<GroupData>) -> AnimData {
+    AnimData {
+        major_version: 2,
+        minor_version,
+        final_frame_index: (FRAME_COUNT - 1) as f32,
+        groups,
+    }
+}
+
+pub fn group(group_type: GroupType, nodes: Vec<NodeData>) -> GroupData {
+    GroupData { group_type, nodes }
+}
+
+pub fn track(name: &str, values: TrackValues) -> TrackData {
+    TrackData {
+        name: name.to_string(),
+        compensate_scale: false,
+        transform_flags: TransformFlags::default(),
+        values,
+    }
+}
+
+pub fn node(name: &str, tracks: Vec<TrackData>) -> NodeData {
+    NodeData {
+        name: name.to_string(),
+        tracks,
+    }
+}
+
+/// The translation values of a Transform track that moves along x, starting at `x`.
+pub fn translations(x: f32) -> TrackValues {
+    TrackValues::Transform(
+        (0..FRAME_COUNT)
+            .map(|frame| Transform {
+                scale: Vector3::new(1.0, 1.0, 1.0),
+                rotation: Vector4::new(0.0, 0.0, 0.0, 1.0),
+                translation: Vector3::new(x + frame as f32, 0.0, 0.0),
+            })
+            .collect(),
+    )
+}
+
+pub fn transform_node(name: &str, x: f32) -> NodeData {
+    node(name, vec![track("Transform", translations(x))])
+}
+
+pub fn visibility_node(name: &str, visible: bool) -> NodeData {
+    node(
+        name,
+        vec![track(
+            "Visibility",
+            TrackValues::Boolean(vec![visible; FRAME_COUNT]),
+        )],
+    )
+}
+
+pub fn material_node(name: &str, track_name: &str, value: f32) -> NodeData {
+    node(
+        name,
+        vec![track(
+            track_name,
+            TrackValues::Vector4(vec![Vector4::new(value, value, value, value); FRAME_COUNT]),
+        )],
+    )
+}
+
+/// A temporary folder that's deleted along with everything in it when dropped.
+pub struct TestDir {
+    pub path: PathBuf,
+}
+
+impl TestDir {
+    pub fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "wifisafe_anim_splicer_test_{}_{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// Encodes the anim with ssbh_data and writes it to `file_name` in this folder.
+    pub fn write_anim(&self, file_name: &str, anim_data: &AnimData) -> PathBuf {
+        let path = self.path.join(file_name);
+        Anim::try_from(anim_data)
+            .unwrap()
+            .write_to_file(&path)
+            .unwrap();
+        path
+    }
+
+    /// Encodes the anims with ssbh_data and writes them to the zip file `file_name` in this folder, by their path in the zip.
+    pub fn write_zip(&self, file_name: &str, anims: &[(&str, &AnimData)]) -> PathBuf {
+        let path = self.path.join(file_name);
+        let files = anims
+            .iter()
+            .map(|(entry, anim_data)| {
+                let anim = Anim::try_from(*anim_data).unwrap();
+                (entry.to_string(), archive::anim_bytes(&anim).unwrap())
+            })
+            .collect::<Vec<_>>();
+        archive::write_zip(&path, &files).unwrap();
+        path
+    }
+
+    /// Writes the anim as ssbh_data's JSON to `file_name` in this folder.
+    pub fn write_json(&self, file_name: &str, anim_data: &AnimData) -> PathBuf {
+        let path = self.path.join(file_name);
+        fs::write(&path, serde_json::to_string(anim_data).unwrap()).unwrap();
+        path
+    }
+}
+
+impl Default for TestDir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,262 @@
+//! The rules that decide whether a modified anim is safe to use online, shared by the validator and the
+//! splicer's watch mode so both always agree.
+
+use anyhow::Result;
+use ssbh_data::anim_data::{GroupData, GroupType, NodeData, TrackValues};
+use ssbh_data::prelude::*;
+use std::collections::HashMap;
+use std::iter::zip;
+use std::path::Path;
+
+use crate::acmd::HitboxBones;
+use crate::archive;
+
+/// A bone can keep byte-identical local Transform data while its parent changed, which still moves it in world space.
+pub struct WorldSpaceCheck {
+    pub skeleton: SkelData,
+    pub protected_bones: Vec<String>,
+    /// The collision bones of dumped fighter scripts, which are also protected in the anims of their motion.
+    pub hitbox_bones: Option<HitboxBones>,
+    /// The fighter of the anims in `hitbox_bones`, when it can't be told from their paths.
+    pub fighter: Option<String>,
+}
+
+impl WorldSpaceCheck {
+    /// The bones to check in the anim, by skeleton name. Every bone of the vanilla anim is checked if this is empty.
+    pub fn protected_bones_for(&self, anim_path: &Path) -> Result<Vec<String>> {
+        let mut protected_bones = self.protected_bones.clone();
+        if let Some(hitbox_bones) = &self.hitbox_bones {
+            for hitbox_bone in hitbox_bones.bones_for_anim(self.fighter.as_deref(), &[anim_path])? {
+                // Scripts write bone names in lowercase.
+                if let Some(bone) = self
+                    .skeleton
+                    .bones
+                    .iter()
+                    .find(|bone| bone.name.eq_ignore_ascii_case(hitbox_bone))
+                {
+                    protected_bones.push(bone.name.clone());
+                }
+            }
+        }
+        Ok(protected_bones)
+    }
+
+    /// Checks that every protected bone of the modified anim, and every ancestor of one, is the same as in the vanilla anim,
+    /// since a changed ancestor still moves the bone in world space.
+    ///
+    /// `vanilla_bones` are all protected when there are no protected bones. `bone_matches` tells whether a bone is the same
+    /// in both anims, or missing from both, which depends on what's known about the vanilla anim.
+    pub fn validate<'a>(
+        &self,
+        modified_anim_path: &Path,
+        vanilla_bones: impl IntoIterator<Item = &'a str>,
+        bone_matches: impl Fn(&str) -> bool,
+    ) -> SafetyRating {
+        let protected_bones = match self.protected_bones_for(modified_anim_path) {
+            Ok(protected_bones) => protected_bones,
+            Err(e) => {
+                return SafetyRating::Warning(format!(
+                    "The protected bones could not be found, error=`{e:#}`"
+                ))
+            }
+        };
+        let protected_bones: Vec<&str> = if protected_bones.is_empty() {
+            vanilla_bones.into_iter().collect()
+        } else {
+            protected_bones.iter().map(String::as_str).collect()
+        };
+
+        for protected_bone in protected_bones {
+            if !bone_matches(protected_bone) {
+                return SafetyRating::Unsafe(format!(
+                    "The protected bone `{protected_bone}` has different transform data in the vanilla vs the modified!"
+                ));
+            }
+            for ancestor in get_ancestors(&self.skeleton, protected_bone) {
+                if !bone_matches(ancestor) {
+                    return SafetyRating::Unsafe(format!(
+                        "The protected bone `{protected_bone}` matches, but its ancestor `{ancestor}` differs in the vanilla vs the modified, so it moves in world space!"
+                    ));
+                }
+            }
+        }
+
+        SafetyRating::Safe
+    }
+}
+
+/// How safe a modified anim is to use online, compared to the vanilla anim.
+pub enum SafetyRating {
+    Safe,
+    Unsafe(String),
+    Warning(String),
+}
+
+pub fn get_group_by_type(anim_data: &AnimData, group_type: GroupType) -> Option<&GroupData> {
+    anim_data
+        .groups
+        .iter()
+        .find(|group| group.group_type == group_type)
+}
+
+/// The ancestors of a bone, starting from its parent and ending at the root.
+pub fn get_ancestors<'a>(skeleton: &'a SkelData, bone_name: &str) -> Vec<&'a str> {
+    let mut ancestors = Vec::new();
+    let mut current = skeleton.bones.iter().find(|bone| bone.name == bone_name);
+    while let Some(parent) = current
+        .and_then(|bone| bone.parent_index)
+        .and_then(|parent_index| skeleton.bones.get(parent_index))
+    {
+        // A malformed skeleton could have a parent cycle, so never walk more than every bone.
+        if ancestors.len() >= skeleton.bones.len() {
+            break;
+        }
+        ancestors.push(parent.name.as_str());
+        current = Some(parent);
+    }
+    ancestors
+}
+
+pub fn get_transform_values<'a>(group: &'a GroupData, node_name: &str) -> Option<&'a TrackValues> {
+    group
+        .nodes
+        .iter()
+        .find(|node| node.name == node_name)?
+        .tracks
+        .first()
+        .map(|track| &track.values)
+}
+
+/// Reads an anim with ssbh_data, also from inside a zip file.
+pub fn read_anim_data(path: &Path) -> Result<AnimData> {
+    let anim = archive::read_anim(path)?;
+    Ok(AnimData::try_from(&anim)?)
+}
+
+pub fn validate_anim(
+    reference_anim_path: &Path,
+    modified_anim_path: &Path,
+    world_space_check: Option<&WorldSpaceCheck>,
+) -> SafetyRating {
+    let reference_anim = match read_anim_data(reference_anim_path) {
+        Ok(anim) => anim,
+        Err(e) => {
+            return SafetyRating::Warning(format!(
+                "Reference anim could not be opened by ssbh_data, error=`{:#}`",
+                e
+            ));
+        }
+    };
+
+    let modified_anim = match read_anim_data(modified_anim_path) {
+        Ok(anim) => anim,
+        Err(e) => {
+            return SafetyRating::Warning(format!(
+                "Modified anim could not be opened by ssbh_data, error=`{:#}`",
+                e
+            ));
+        }
+    };
+
+    if reference_anim.final_frame_index != modified_anim.final_frame_index {
+        return SafetyRating::Unsafe(
+            format!(
+                "The modified anim has a final_frame_index of `{}`, while the matching vanilla anim has a final_frame_index of `{}`",
+                modified_anim.final_frame_index,
+                reference_anim.final_frame_index
+            )
+        );
+    }
+
+    let (ref_trans_group, mod_trans_group) = match (get_group_by_type(&reference_anim, GroupType::Transform), get_group_by_type(&modified_anim, GroupType::Transform)){
+        (Some(ref_group), Some(mod_group)) => {(ref_group, mod_group)},
+        (Some(_ref_group), None) => {return SafetyRating::Unsafe("The reference anim has a transform group, but the modified group has no transform group!".to_string())},
+        (None, Some(_mod_group)) => {return SafetyRating::Warning("The modified anim has transform data, but the vanilla anim had none! As long as you're 100% sure you didn't mess with any vanilla hitbox/hurtbox bones, this can still be ok.".to_string())},
+        (None, None) => {return SafetyRating::Safe}
+    };
+
+    let mod_nodes_by_name: HashMap<String, &NodeData> = mod_trans_group
+        .nodes
+        .iter()
+        .map(|x| (x.name.clone(), x))
+        .collect();
+
+    // For the Transform group, each Node corresponds to a bone.
+    // Each bone Node will only have one Track, which is it's transform values.
+    for reference_node in &ref_trans_group.nodes {
+        let reference_values = match reference_node.tracks.first() {
+            None => {
+                println!("The reference anim {:?} has a Node for bone `{}` with no transform Track at all! Skipping this bone..", reference_anim_path.file_name().unwrap_or_default(), reference_node.name);
+                continue;
+            }
+            Some(track) => {
+                match &track.values {
+                    TrackValues::Transform(values) => values,
+                    _ => {
+                        /* Some vanilla anims like
+                        fighter/kirby/motion/jackbody/c00/jackd00specialairnrandomend.nuanmb
+                        are poorly formatted like this.
+                        */
+                        println!("The reference anim `{:?}` is poorly formatted and has vis or mat data in the transform data. Skipping that track...", reference_anim_path.file_name().unwrap_or_default());
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let modified_node = match mod_nodes_by_name.get(&reference_node.name) {
+            Some(node) => node,
+            None => {
+                return SafetyRating::Unsafe(format!(
+                    "Modified anim missing transform node `{}`",
+                    reference_node.name
+                ))
+            }
+        };
+
+        let modified_values = match modified_node.tracks.first() {
+            None => {return SafetyRating::Unsafe(format!("The modified anim is missing the Transform Track for Node `{}`", modified_node.name))},
+            Some(track) => match &track.values{
+                TrackValues::Transform(values) => {values},
+                _ => {return SafetyRating::Unsafe(format!("The modified anim is poorly formatted and has vis or mat data instead of transform data for Node `{}`", modified_node.name))}
+            }
+        };
+
+        if reference_values.len() != modified_values.len() {
+            return SafetyRating::Unsafe(format!(
+                "The Node `{}` has different amount of values in the vanilla vs the modified! Vanilla=`{}`, Modified=`{}`", 
+                modified_node.name,
+                reference_values.len(),
+                modified_values.len()));
+        }
+
+        for (index, (reference_value, modified_value)) in
+            zip(reference_values.iter(), modified_values.iter()).enumerate()
+        {
+            if reference_value != modified_value {
+                return SafetyRating::Unsafe(
+                    format!(
+                        "The Node `{}` at frame `{}` has differing values! Vanilla=`{:?}`, Modified=`{:?}`",
+                        modified_node.name,
+                        index,
+                        reference_value,
+                        modified_value,
+                    )
+                );
+            }
+        }
+    }
+
+    if let Some(world_space_check) = world_space_check {
+        return world_space_check.validate(
+            modified_anim_path,
+            ref_trans_group.nodes.iter().map(|node| node.name.as_str()),
+            |name| {
+                get_transform_values(ref_trans_group, name)
+                    == get_transform_values(mod_trans_group, name)
+            },
+        );
+    }
+
+    SafetyRating::Safe
+}
@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wifisafe_common::hash::{hash_file, sha256_hex};
+
+use crate::name_conflicts::NameConflictPolicy;
+use crate::node_order::NodeOrder;
+use crate::retime::FrameMapping;
+use crate::SpliceOptions;
+
+/// The manifest is kept in the output folder, next to the anims it describes.
+const MANIFEST_FILE_NAME: &str = ".wifisafe_splice_cache.json";
+
+/// The options that change the bytes of a spliced anim. The skeleton and round trip check only add reports, so they're left out.
+#[derive(Serialize)]
+struct OutputOptions<'a> {
+    /// A new version of the splicer can lay anims out differently, so it counts as an option too.
+    version: &'static str,
+    added_bone_frame_mapping: Option<&'a FrameMapping>,
+    source_rules: Vec<String>,
+    /// The locked bones of the anim, since `--acmd_dump` locks different bones in each motion.
+    locked_bones: Vec<String>,
+    node_order: NodeOrder,
+    name_conflicts: NameConflictPolicy,
+    buffer_alignment: Option<usize>,
+    deduplicate_tracks: bool,
+}
+
+impl<'a> OutputOptions<'a> {
+    fn new(options: &'a SpliceOptions, locked_bones: Vec<String>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            added_bone_frame_mapping: options.added_bone_frame_mapping.as_ref(),
+            source_rules: options
+                .source_rules
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            locked_bones,
+            node_order: options.node_order,
+            name_conflicts: options.name_conflicts,
+            buffer_alignment: options.buffer_alignment,
+            deduplicate_tracks: options.deduplicate_tracks,
+        }
+    }
+
+    fn hash(&self) -> Result<String> {
+        let json = serde_json::to_string(self).context("could not serialize the splice options")?;
+        Ok(sha256_hex(json.as_bytes()))
+    }
+}
+
+/// Everything that goes into a spliced anim.
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct CacheInputs {
+    reference: String,
+    modified: Vec<Option<String>>,
+    options: String,
+}
+
+impl CacheInputs {
+    pub fn new(
+        reference_anim_path: &Path,
+        modified_anim_paths: &[Option<PathBuf>],
+        options: &SpliceOptions,
+    ) -> Result<Self> {
+        Ok(Self {
+            reference: hash_file(reference_anim_path)?,
+            modified: modified_anim_paths
+                .iter()
+                .map(|path| path.as_deref().map(hash_file).transpose())
+                .collect::<Result<_>>()?,
+            options: OutputOptions::new(
+                options,
+                options.locked_bones_for(reference_anim_path, modified_anim_paths)?,
+            )
+            .hash()?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    inputs: CacheInputs,
+    output: String,
+}
+
+/// The inputs of every anim written to an output folder, so unchanged anims don't have to be spliced again.
+#[derive(Default, Serialize, Deserialize)]
+pub struct BuildCache {
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+impl BuildCache {
+    /// Reads the manifest of the output folder, or starts an empty one if it's missing or unreadable.
+    pub fn load(output_dir: &Path) -> Self {
+        let path = output_dir.join(MANIFEST_FILE_NAME);
+        let Ok(json) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            println!(
+                "WARNING: could not parse the build cache `{}`, so every anim will be spliced again. error=`{e}`",
+                path.display()
+            );
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        let path = output_dir.join(MANIFEST_FILE_NAME);
+        let json =
+            serde_json::to_string_pretty(self).context("could not serialize the build cache")?;
+        fs::write(&path, json)
+            .with_context(|| format!("could not write the build cache `{}`", path.display()))
+    }
+
+    /// Whether the output was spliced from exactly these inputs and hasn't been changed since.
+    pub fn is_up_to_date(&self, output_path: &Path, inputs: &CacheInputs) -> bool {
+        let Some(entry) = self.entries.get(&Self::key(output_path)) else {
+            return false;
+        };
+        entry.inputs == *inputs && hash_file(output_path).is_ok_and(|output| output == entry.output)
+    }
+
+    pub fn insert(&mut self, output_path: &Path, inputs: CacheInputs) -> Result<()> {
+        let output = hash_file(output_path)?;
+        self.entries
+            .insert(Self::key(output_path), CacheEntry { inputs, output });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, output_path: &Path) {
+        self.entries.remove(&Self::key(output_path));
+    }
+
+    fn key(output_path: &Path) -> String {
+        output_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wifisafe_common::acmd::HitboxBones;
+    use wifisafe_common::test_anims::TestDir;
+
+    #[test]
+    fn options_hash_only_changes_with_the_output() {
+        let options = SpliceOptions::default();
+        let hash = OutputOptions::new(&options, Vec::new()).hash().unwrap();
+
+        let report_only = SpliceOptions {
+            round_trip_check: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            OutputOptions::new(&report_only, Vec::new()).hash().unwrap(),
+            hash
+        );
+
+        let reordered = SpliceOptions {
+            node_order: NodeOrder::Reference,
+            ..Default::default()
+        };
+        assert_ne!(
+            OutputOptions::new(&reordered, Vec::new()).hash().unwrap(),
+            hash
+        );
+
+        let aligned = SpliceOptions {
+            buffer_alignment: Some(16),
+            ..Default::default()
+        };
+        assert_ne!(
+            OutputOptions::new(&aligned, Vec::new()).hash().unwrap(),
+            hash
+        );
+    }
+
+    #[test]
+    fn locked_bones_of_the_motion_change_the_options_hash() {
+        let mut hitbox_bones = HitboxBones::default();
+        hitbox_bones.parse_scripts(
+            "mario",
+            "fn game_attack11() { ATTACK(0, 0, Hash40::new(\"armr\")) } fn game_wait1() {}",
+        );
+        let options = SpliceOptions {
+            hitbox_bones: Some(hitbox_bones),
+            ..Default::default()
+        };
+
+        let dir = TestDir::new();
+        let inputs = |file_name: &str| {
+            let reference = dir.path.join(file_name);
+            fs::write(&reference, [1]).unwrap();
+            CacheInputs::new(&reference, &[None], &options).unwrap()
+        };
+        assert_ne!(
+            inputs("c02attack11.nuanmb").options,
+            inputs("a00wait1.nuanmb").options
+        );
+    }
+
+    #[test]
+    fn changed_inputs_or_outputs_are_not_up_to_date() {
+        let dir = TestDir::new();
+        let reference = dir.path.join("reference.nuanmb");
+        let modified = dir.path.join("modified.nuanmb");
+        let output = dir.path.join("a00wait1.nuanmb");
+        fs::write(&reference, [1]).unwrap();
+        fs::write(&modified, [2]).unwrap();
+        fs::write(&output, [3]).unwrap();
+
+        let options = SpliceOptions::default();
+        let inputs = || CacheInputs::new(&reference, &[Some(modified.clone())], &options).unwrap();
+
+        let mut cache = BuildCache::default();
+        assert!(!cache.is_up_to_date(&output, &inputs()));
+        cache.insert(&output, inputs()).unwrap();
+        assert!(cache.is_up_to_date(&output, &inputs()));
+
+        // The cache is kept in the output folder between runs.
+        cache.save(&dir.path).unwrap();
+        let cache = BuildCache::load(&dir.path);
+        assert!(cache.is_up_to_date(&output, &inputs()));
+
+        fs::write(&modified, [4]).unwrap();
+        assert!(!cache.is_up_to_date(&output, &inputs()));
+        fs::write(&modified, [2]).unwrap();
+
+        fs::write(&output, [5]).unwrap();
+        assert!(!cache.is_up_to_date(&output, &inputs()));
+    }
+
+    #[test]
+    fn removed_outputs_are_not_up_to_date() {
+        let dir = TestDir::new();
+        let reference = dir.path.join("reference.nuanmb");
+        let output = dir.path.join("a00wait1.nuanmb");
+        fs::write(&reference, [1]).unwrap();
+        fs::write(&output, [2]).unwrap();
+
+        let options = SpliceOptions::default();
+        let inputs = CacheInputs::new(&reference, &[None], &options).unwrap();
+        let mut cache = BuildCache::default();
+        cache.insert(&output, inputs).unwrap();
+
+        let inputs = CacheInputs::new(&reference, &[None], &options).unwrap();
+        cache.remove(&output);
+        assert!(!cache.is_up_to_date(&output, &inputs));
+    }
+}
@@ -0,0 +1,343 @@
+use anyhow::{Context, Result};
+use ssbh_data::anim_data::{AnimData, TrackValues};
+use ssbh_lib::formats::anim::{Group, GroupType, TrackV2};
+use ssbh_lib::{prelude::*, SsbhArray, SsbhByteBuffer};
+use std::path::Path;
+use wifisafe_common::anim::{find_node, track_data};
+use wifisafe_common::archive;
+
+use crate::find_group;
+
+enum NodeDifference {
+    OnlyInReference,
+    OnlyInModified,
+    TrackOnlyInReference(String),
+    TrackOnlyInModified(String),
+    TrackBytesDiffer(String),
+    TrackValuesDiffer { track: String, frame: usize },
+}
+
+struct NodeDiff {
+    group_type: GroupType,
+    node_name: String,
+    difference: NodeDifference,
+}
+
+fn get_groups_and_buffer(anim: &Anim) -> Option<(&SsbhArray<Group>, &SsbhByteBuffer)> {
+    match anim {
+        Anim::V20 { groups, buffer, .. } | Anim::V21 { groups, buffer, .. } => {
+            Some((groups, buffer))
+        }
+        Anim::V12 { .. } => None,
+    }
+}
+
+/// Compares everything about two tracks except where their data happens to live in the buffer.
+fn tracks_are_byte_identical(
+    reference_track: &TrackV2,
+    reference_buffer: &SsbhByteBuffer,
+    modified_track: &TrackV2,
+    modified_buffer: &SsbhByteBuffer,
+) -> bool {
+    let metadata_matches = TrackV2 {
+        data_offset: 0,
+        ..reference_track.clone()
+    } == TrackV2 {
+        data_offset: 0,
+        ..modified_track.clone()
+    };
+    let reference_bytes = track_data(reference_track, reference_buffer);
+    metadata_matches
+        && reference_bytes.is_some()
+        && reference_bytes == track_data(modified_track, modified_buffer)
+}
+
+fn first_differing_index<T: PartialEq>(reference: &[T], modified: &[T]) -> Option<usize> {
+    reference
+        .iter()
+        .zip(modified)
+        .position(|(reference_value, modified_value)| reference_value != modified_value)
+        .or_else(|| {
+            (reference.len() != modified.len()).then(|| reference.len().min(modified.len()))
+        })
+}
+
+/// Returns the first frame where the decoded values differ, or `None` if they're identical.
+fn first_differing_frame(reference: &TrackValues, modified: &TrackValues) -> Option<usize> {
+    match (reference, modified) {
+        (TrackValues::Transform(r), TrackValues::Transform(m)) => first_differing_index(r, m),
+        (TrackValues::UvTransform(r), TrackValues::UvTransform(m)) => first_differing_index(r, m),
+        (TrackValues::Float(r), TrackValues::Float(m)) => first_differing_index(r, m),
+        (TrackValues::PatternIndex(r), TrackValues::PatternIndex(m)) => first_differing_index(r, m),
+        (TrackValues::Boolean(r), TrackValues::Boolean(m)) => first_differing_index(r, m),
+        (TrackValues::Vector4(r), TrackValues::Vector4(m)) => first_differing_index(r, m),
+        _ => Some(0),
+    }
+}
+
+fn find_decoded_values<'a>(
+    anim_data: Option<&'a AnimData>,
+    group_type: GroupType,
+    node_name: &str,
+    track_name: &str,
+) -> Option<&'a TrackValues> {
+    anim_data?
+        .groups
+        .iter()
+        .find(|group| group.group_type == group_type)?
+        .nodes
+        .iter()
+        .find(|node| node.name == node_name)?
+        .tracks
+        .iter()
+        .find(|track| track.name == track_name)
+        .map(|track| &track.values)
+}
+
+fn decode_anim(anim: &Anim, path: &Path) -> Option<AnimData> {
+    match AnimData::try_from(anim) {
+        Ok(anim_data) => Some(anim_data),
+        Err(e) => {
+            println!(
+                "WARNING: {path:?} could not be decoded by ssbh_data, error=`{e}`. Decoded values will not be compared."
+            );
+            None
+        }
+    }
+}
+
+/// Lists every node and track that differs between the two anims, without judging whether the differences are safe.
+fn diff_anims(reference_anim_path: &Path, modified_anim_path: &Path) -> Result<Vec<NodeDiff>> {
+    let reference_anim = archive::read_anim(reference_anim_path).with_context(|| {
+        format!(
+            "could not read reference anim `{}`",
+            &reference_anim_path.display()
+        )
+    })?;
+
+    let modified_anim = archive::read_anim(modified_anim_path).with_context(|| {
+        format!(
+            "could not read modified anim `{}`",
+            &modified_anim_path.display()
+        )
+    })?;
+
+    let (reference_groups, reference_buffer) = get_groups_and_buffer(&reference_anim)
+        .ok_or_else(|| anyhow::format_err!("v12 reference anim not supported!"))?;
+    let (modified_groups, modified_buffer) = get_groups_and_buffer(&modified_anim)
+        .ok_or_else(|| anyhow::format_err!("v12 modified anim not supported!"))?;
+
+    let reference_anim_data = decode_anim(&reference_anim, reference_anim_path);
+    let modified_anim_data = decode_anim(&modified_anim, modified_anim_path);
+
+    let mut group_types: Vec<GroupType> = Vec::new();
+    for group in reference_groups
+        .elements
+        .iter()
+        .chain(modified_groups.elements.iter())
+    {
+        if !group_types.contains(&group.group_type) {
+            group_types.push(group.group_type);
+        }
+    }
+
+    let mut diffs = Vec::new();
+    for group_type in group_types {
+        let reference_group = find_group(reference_groups, group_type);
+        let modified_group = find_group(modified_groups, group_type);
+
+        let reference_nodes = reference_group.map(|g| g.nodes.elements.as_slice());
+        for reference_node in reference_nodes.unwrap_or_default() {
+            let node_name = reference_node.name.to_string_lossy();
+            let modified_node =
+                match modified_group.and_then(|group| find_node(group, &reference_node.name)) {
+                    Some(node) => node,
+                    None => {
+                        diffs.push(NodeDiff {
+                            group_type,
+                            node_name,
+                            difference: NodeDifference::OnlyInReference,
+                        });
+                        continue;
+                    }
+                };
+
+            for reference_track in &reference_node.tracks.elements {
+                let track_name = reference_track.name.to_string_lossy();
+                let modified_track = match modified_node
+                    .tracks
+                    .elements
+                    .iter()
+                    .find(|track| track.name == reference_track.name)
+                {
+                    Some(track) => track,
+                    None => {
+                        diffs.push(NodeDiff {
+                            group_type,
+                            node_name: node_name.clone(),
+                            difference: NodeDifference::TrackOnlyInReference(track_name),
+                        });
+                        continue;
+                    }
+                };
+
+                if tracks_are_byte_identical(
+                    reference_track,
+                    reference_buffer,
+                    modified_track,
+                    modified_buffer,
+                ) {
+                    continue;
+                }
+
+                diffs.push(NodeDiff {
+                    group_type,
+                    node_name: node_name.clone(),
+                    difference: NodeDifference::TrackBytesDiffer(track_name.clone()),
+                });
+
+                let reference_values = find_decoded_values(
+                    reference_anim_data.as_ref(),
+                    group_type,
+                    &node_name,
+                    &track_name,
+                );
+                let modified_values = find_decoded_values(
+                    modified_anim_data.as_ref(),
+                    group_type,
+                    &node_name,
+                    &track_name,
+                );
+                if let (Some(reference_values), Some(modified_values)) =
+                    (reference_values, modified_values)
+                {
+                    if let Some(frame) = first_differing_frame(reference_values, modified_values) {
+                        diffs.push(NodeDiff {
+                            group_type,
+                            node_name: node_name.clone(),
+                            difference: NodeDifference::TrackValuesDiffer {
+                                track: track_name,
+                                frame,
+                            },
+                        });
+                    }
+                }
+            }
+
+            for modified_track in &modified_node.tracks.elements {
+                let track_name = modified_track.name.to_string_lossy();
+                if !reference_node
+                    .tracks
+                    .elements
+                    .iter()
+                    .any(|track| track.name == modified_track.name)
+                {
+                    diffs.push(NodeDiff {
+                        group_type,
+                        node_name: node_name.clone(),
+                        difference: NodeDifference::TrackOnlyInModified(track_name),
+                    });
+                }
+            }
+        }
+
+        let modified_nodes = modified_group.map(|g| g.nodes.elements.as_slice());
+        for modified_node in modified_nodes.unwrap_or_default() {
+            if reference_group
+                .and_then(|group| find_node(group, &modified_node.name))
+                .is_none()
+            {
+                diffs.push(NodeDiff {
+                    group_type,
+                    node_name: modified_node.name.to_string_lossy(),
+                    difference: NodeDifference::OnlyInModified,
+                });
+            }
+        }
+    }
+
+    Ok(diffs)
+}
+
+/// Returns why a spliced anim doesn't keep every vanilla Transform track byte for byte, or `None` if it does.
+///
+/// The validator only compares decoded values, which can match even when the splicer changed the bytes.
+pub fn find_changed_vanilla_track(
+    reference_anim_path: &Path,
+    spliced_anim_path: &Path,
+) -> Result<Option<String>> {
+    let diffs = diff_anims(reference_anim_path, spliced_anim_path)?;
+    Ok(diffs
+        .iter()
+        .filter(|diff| diff.group_type == GroupType::Transform)
+        .find_map(|diff| match &diff.difference {
+            NodeDifference::OnlyInReference => Some(format!(
+                "The spliced anim is missing the transform node `{}`",
+                diff.node_name
+            )),
+            NodeDifference::TrackOnlyInReference(track) => Some(format!(
+                "The spliced anim is missing the Track `{track}` of the Node `{}`",
+                diff.node_name
+            )),
+            NodeDifference::TrackBytesDiffer(track) => Some(format!(
+                "The Node `{}` has different bytes in the Track `{track}` than the vanilla anim",
+                diff.node_name
+            )),
+            _ => None,
+        }))
+}
+
+pub fn do_diff_mode(reference_anim: &Path, modified_anim: &Path) -> Result<()> {
+    let diffs = diff_anims(reference_anim, modified_anim)?;
+
+    for diff in &diffs {
+        let group_type = diff.group_type;
+        let node_name = &diff.node_name;
+        match &diff.difference {
+            NodeDifference::OnlyInReference => {
+                println!("ONLY IN REFERENCE: Group={group_type:?}, Node=`{node_name}`")
+            }
+            NodeDifference::OnlyInModified => {
+                println!("ONLY IN MODIFIED: Group={group_type:?}, Node=`{node_name}`")
+            }
+            NodeDifference::TrackOnlyInReference(track) => println!(
+                "TRACK ONLY IN REFERENCE: Group={group_type:?}, Node=`{node_name}`, Track=`{track}`"
+            ),
+            NodeDifference::TrackOnlyInModified(track) => println!(
+                "TRACK ONLY IN MODIFIED: Group={group_type:?}, Node=`{node_name}`, Track=`{track}`"
+            ),
+            NodeDifference::TrackBytesDiffer(track) => println!(
+                "BYTES DIFFER: Group={group_type:?}, Node=`{node_name}`, Track=`{track}`"
+            ),
+            NodeDifference::TrackValuesDiffer { track, frame } => println!(
+                "VALUES DIFFER: Group={group_type:?}, Node=`{node_name}`, Track=`{track}`, first differing frame=`{frame}`"
+            ),
+        }
+    }
+
+    let count = |f: fn(&NodeDifference) -> bool| diffs.iter().filter(|d| f(&d.difference)).count();
+    println!(
+        "Nodes Only In Reference: {}",
+        count(|d| matches!(d, NodeDifference::OnlyInReference))
+    );
+    println!(
+        "Nodes Only In Modified: {}",
+        count(|d| matches!(d, NodeDifference::OnlyInModified))
+    );
+    println!(
+        "Tracks Only In One Anim: {}",
+        count(|d| matches!(
+            d,
+            NodeDifference::TrackOnlyInReference(_) | NodeDifference::TrackOnlyInModified(_)
+        ))
+    );
+    println!(
+        "Tracks With Differing Bytes: {}",
+        count(|d| matches!(d, NodeDifference::TrackBytesDiffer(_)))
+    );
+    println!(
+        "Tracks With Differing Values: {}",
+        count(|d| matches!(d, NodeDifference::TrackValuesDiffer { .. }))
+    );
+    Ok(())
+}
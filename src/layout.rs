@@ -0,0 +1,170 @@
+use anyhow::Result;
+use ssbh_lib::{prelude::*, SsbhByteBuffer};
+use std::collections::HashMap;
+
+/// The largest alignment [measure_alignment] reports, so an anim with only a few tracks isn't padded for nothing.
+pub const MAX_MEASURED_ALIGNMENT: usize = 16;
+
+/// Measures the alignment of the anim's own track data, which is the largest power of two up to
+/// [MAX_MEASURED_ALIGNMENT] that divides the `data_offset` of every track.
+///
+/// An offset of 0 fits any alignment, so an anim without any other offsets is treated as unaligned.
+pub fn measure_alignment(anim: &Anim) -> usize {
+    let groups = match anim {
+        Anim::V20 { groups, .. } | Anim::V21 { groups, .. } => groups,
+        Anim::V12 { .. } => return 1,
+    };
+    let offsets = groups
+        .elements
+        .iter()
+        .flat_map(|group| group.nodes.elements.iter())
+        .flat_map(|node| node.tracks.elements.iter())
+        .filter(|track| track.data_size > 0 && track.data_offset > 0)
+        .map(|track| track.data_offset as usize)
+        .collect::<Vec<_>>();
+    if offsets.is_empty() {
+        return 1;
+    }
+
+    let mut alignment = MAX_MEASURED_ALIGNMENT;
+    while alignment > 1 && offsets.iter().any(|offset| offset % alignment != 0) {
+        alignment /= 2;
+    }
+    alignment
+}
+
+/// Builds the spliced anim's buffer one track at a time.
+///
+/// Track data is padded to start on a multiple of `alignment`, and identical track data can optionally
+/// be written once and shared by every track that uses it, which is common for constant visibility tracks.
+/// Vanilla anims aren't known to share track data, so it isn't verified that the game accepts that.
+pub struct BufferBuilder {
+    buffer: Vec<u8>,
+    alignment: usize,
+    offsets_by_data: Option<HashMap<Vec<u8>, u32>>,
+}
+
+impl BufferBuilder {
+    pub fn new(alignment: usize, deduplicate: bool) -> Self {
+        Self {
+            buffer: Vec::new(),
+            alignment: alignment.max(1),
+            offsets_by_data: deduplicate.then(HashMap::new),
+        }
+    }
+
+    /// Adds the track data to the buffer and returns the offset it can be found at.
+    ///
+    /// Fails if the offset wouldn't fit in a `TrackV2.data_offset`.
+    pub fn push(&mut self, data: &[u8]) -> Result<u32> {
+        if let Some(&offset) = self
+            .offsets_by_data
+            .as_ref()
+            .and_then(|offsets| offsets.get(data))
+        {
+            return Ok(offset);
+        }
+
+        let padding = (self.alignment - self.buffer.len() % self.alignment) % self.alignment;
+        let offset = u32::try_from(self.buffer.len() + padding).map_err(|_| {
+            anyhow::format_err!(
+                "the spliced anim's buffer is already `{}` bytes, which is past the largest offset a track can point to",
+                self.buffer.len()
+            )
+        })?;
+
+        self.buffer.resize(self.buffer.len() + padding, 0);
+        self.buffer.extend_from_slice(data);
+        if let Some(offsets) = &mut self.offsets_by_data {
+            offsets.insert(data.to_vec(), offset);
+        }
+        Ok(offset)
+    }
+
+    pub fn into_buffer(self) -> SsbhByteBuffer {
+        SsbhByteBuffer::from_vec(self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssbh_data::anim_data::GroupType;
+    use wifisafe_common::test_anims::*;
+
+    #[test]
+    fn unaligned_data_is_written_back_to_back() {
+        let mut builder = BufferBuilder::new(1, false);
+        assert_eq!(builder.push(&[1, 2, 3]).unwrap(), 0);
+        assert_eq!(builder.push(&[4]).unwrap(), 3);
+        assert_eq!(builder.into_buffer().elements, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn aligned_data_is_padded_with_zeros() {
+        let mut builder = BufferBuilder::new(4, false);
+        assert_eq!(builder.push(&[1, 2, 3]).unwrap(), 0);
+        assert_eq!(builder.push(&[4, 5, 6, 7]).unwrap(), 4);
+        assert_eq!(builder.push(&[8]).unwrap(), 8);
+        assert_eq!(
+            builder.into_buffer().elements,
+            vec![1, 2, 3, 0, 4, 5, 6, 7, 8]
+        );
+    }
+
+    #[test]
+    fn zero_alignment_is_unaligned() {
+        let mut builder = BufferBuilder::new(0, false);
+        builder.push(&[1]).unwrap();
+        assert_eq!(builder.push(&[2]).unwrap(), 1);
+    }
+
+    #[test]
+    fn identical_data_is_only_shared_when_deduplicating() {
+        let mut builder = BufferBuilder::new(2, true);
+        assert_eq!(builder.push(&[1, 1, 1]).unwrap(), 0);
+        assert_eq!(builder.push(&[2]).unwrap(), 4);
+        assert_eq!(builder.push(&[1, 1, 1]).unwrap(), 0);
+        assert_eq!(builder.into_buffer().elements, vec![1, 1, 1, 0, 2]);
+
+        let mut builder = BufferBuilder::new(2, false);
+        assert_eq!(builder.push(&[1, 1, 1]).unwrap(), 0);
+        assert_eq!(builder.push(&[1, 1, 1]).unwrap(), 4);
+        assert_eq!(builder.into_buffer().elements, vec![1, 1, 1, 0, 1, 1, 1]);
+    }
+
+    /// An anim with one Transform track per offset, pointing at the given offsets.
+    fn anim_with_offsets(offsets: &[u32]) -> Anim {
+        let nodes = (0..offsets.len())
+            .map(|i| transform_node(&format!("Bone{i}"), i as f32))
+            .collect();
+        let mut anim =
+            Anim::try_from(&anim_data(1, vec![group(GroupType::Transform, nodes)])).unwrap();
+        if let Anim::V21 { groups, .. } = &mut anim {
+            let tracks = groups
+                .elements
+                .iter_mut()
+                .flat_map(|group| group.nodes.elements.iter_mut())
+                .flat_map(|node| node.tracks.elements.iter_mut());
+            for (track, &offset) in tracks.zip(offsets) {
+                track.data_offset = offset;
+            }
+        }
+        anim
+    }
+
+    #[test]
+    fn alignment_is_measured_from_the_track_offsets() {
+        assert_eq!(measure_alignment(&anim_with_offsets(&[0, 16, 48])), 16);
+        assert_eq!(measure_alignment(&anim_with_offsets(&[0, 8, 48])), 8);
+        assert_eq!(measure_alignment(&anim_with_offsets(&[0, 6, 12])), 2);
+        assert_eq!(measure_alignment(&anim_with_offsets(&[0, 7])), 1);
+        // Offsets past the largest alignment are capped.
+        assert_eq!(
+            measure_alignment(&anim_with_offsets(&[0, 64, 128])),
+            MAX_MEASURED_ALIGNMENT
+        );
+        // An offset of 0 says nothing about the alignment.
+        assert_eq!(measure_alignment(&anim_with_offsets(&[0])), 1);
+    }
+}
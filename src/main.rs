@@ -1,50 +1,273 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use cache::{BuildCache, CacheInputs};
+use clap::{Parser, Subcommand};
 use itertools::Itertools;
+use ssbh_data::anim_data::AnimData;
 use ssbh_lib::formats::anim::{Group, GroupType, Node, TrackV2};
-use ssbh_lib::{prelude::*, SsbhArray, SsbhByteBuffer};
-use std::fs;
+use ssbh_lib::{prelude::*, SsbhArray, SsbhByteBuffer, SsbhString};
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+mod cache;
+mod diff;
+mod layout;
+mod name_conflicts;
+mod node_order;
+mod output;
+mod report;
+mod retime;
+mod round_trip;
+mod skeleton;
+mod sources;
+mod track_ranges;
+mod watch;
+
+#[cfg(test)]
+mod tests;
+
+use layout::BufferBuilder;
+use name_conflicts::{
+    check_spliced_name_conflicts, resolve_name_conflicts, warn_about_reference_name_conflicts,
+    NameConflictPolicy,
+};
+use node_order::NodeOrder;
+use output::{check_output_path, write_output, BatchOutput};
+use report::{
+    BatchReport, CopiedEntry, FailedEntry, SkippedEntry, SpliceStats, SplicedEntry, UnchangedEntry,
+};
+use retime::FrameMapping;
+use skeleton::Skeleton;
+use sources::{source_order, SourceRule};
+use track_ranges::{check_track_ranges, report_transform_node_layouts};
+use wifisafe_common::acmd::HitboxBones;
+use wifisafe_common::anim::{find_node, track_data};
+use wifisafe_common::archive::{
+    self, is_json_path, is_nuanmb_path, is_zip_path, read_anim_dir, zip_entry_dir,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about=None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(short = 'r', long)]
     reference_anim_file: Option<PathBuf>,
-    #[arg(short = 'm', long)]
-    modified_anim_file: Option<PathBuf>,
+    #[arg(short = 'm', long, num_args = 1..)]
+    modified_anim_file: Vec<PathBuf>,
     #[arg(short = 'o', long)]
     output_file: Option<PathBuf>,
     #[arg(long = "reference_folder")]
     batch_reference_folder: Option<PathBuf>,
-    #[arg(long = "modified_folder")]
-    batch_modified_folder: Option<PathBuf>,
+    #[arg(long = "modified_folder", num_args = 1..)]
+    batch_modified_folder: Vec<PathBuf>,
     #[arg(long = "output_folder")]
     batch_output_folder: Option<PathBuf>,
+    /// Decode added bones with ssbh_data and remap their frames instead of copying their bytes
+    #[arg(long = "retime_added_bones")]
+    retime_added_bones: bool,
+    /// Shift the added bones by this many frames (requires --retime_added_bones)
+    #[arg(long = "frame_offset", default_value_t = 0.0, value_parser = retime::parse_frame_offset, requires = "retime_added_bones")]
+    frame_offset: f32,
+    /// Play the added bones this many times faster (requires --retime_added_bones)
+    #[arg(long = "frame_scale", default_value_t = 1.0, value_parser = retime::parse_frame_scale, requires = "retime_added_bones")]
+    frame_scale: f32,
+    /// Drop the added bones' frames before this frame (requires --retime_added_bones)
+    #[arg(long = "trim_start", requires = "retime_added_bones")]
+    trim_start: Option<usize>,
+    /// Drop the added bones' frames after this frame (requires --retime_added_bones)
+    #[arg(long = "trim_end", requires = "retime_added_bones")]
+    trim_end: Option<usize>,
+    /// Loop the added bones instead of holding their first/last frame (requires --retime_added_bones)
+    #[arg(long = "loop_added_bones", requires = "retime_added_bones")]
+    loop_added_bones: bool,
+    /// Take a group or node from a specific modified anim/folder, e.g. `visibility=2` or `transform/HelperBone=1`
+    #[arg(long = "source_rule")]
+    source_rules: Vec<SourceRule>,
+    /// The fighter's `.nusktb`, used to report where each added or locked bone sits in the hierarchy and warn about risky splices. The `.nuhlpb` isn't read
+    #[arg(long = "skeleton")]
+    skeleton_file: Option<PathBuf>,
+    /// Never add these bones from a modified anim, like hitbox bones. The bones of the reference anim are always kept as is
+    #[arg(long = "locked_bones", num_args = 1..)]
+    locked_bones: Vec<String>,
+    /// Also lock every bone with a hitbox, grab box, search box or hurtbox in these dumped fighter scripts, a file or folder
+    #[arg(long = "acmd_dump")]
+    acmd_dump: Option<PathBuf>,
+    /// The fighter of the anims in --acmd_dump, like `mario`. Only needed when the scripts have several fighters and the anims aren't in a `fighter/<name>` folder
+    #[arg(long = "fighter")]
+    fighter: Option<String>,
+    /// How to order the Transform nodes of the spliced anim
+    #[arg(long = "node_order", value_enum, default_value_t)]
+    node_order: NodeOrder,
+    /// What to do when a group has duplicate or case-colliding node names, like `ArmR` and `armr`
+    #[arg(long = "name_conflicts", value_enum, default_value_t)]
+    name_conflicts: NameConflictPolicy,
+    /// Start each track's data on a multiple of this many bytes in the spliced anim's buffer. Defaults to the alignment measured from the reference anim's track data
+    #[arg(long = "buffer_alignment")]
+    buffer_alignment: Option<usize>,
+    /// Store identical track data only once and point every track that uses it at the same bytes. Vanilla anims aren't known to do this, so test the results in game
+    #[arg(long = "deduplicate_tracks")]
+    deduplicate_tracks: bool,
+    /// Warn when splicing a reference anim with itself doesn't reproduce it byte for byte
+    #[arg(long = "round_trip_check")]
+    round_trip_check: bool,
+    /// Also write the batch mode summary, with every spliced, skipped and failed anim, to this JSON file
+    #[arg(long = "report")]
+    batch_report_file: Option<PathBuf>,
+    /// Only splice the batch mode anims whose reference, modified anims or options changed since the last run
+    #[arg(long = "incremental")]
+    incremental: bool,
+    /// Copy modified anims that have no vanilla anim to the output folder unchanged, instead of skipping them
+    #[arg(long = "copy_unmatched")]
+    copy_unmatched: bool,
+    /// Allow the output to be one of the inputs, backing up every overwritten file as `.bak` first
+    #[arg(long = "in_place")]
+    in_place: bool,
+    /// Keep running batch mode, splicing and validating the anims again whenever the folders change (implies --incremental)
+    #[arg(long = "watch")]
+    watch: bool,
+    /// How often to check the folders for changes in watch mode, in seconds
+    #[arg(long = "watch_interval", default_value_t = 1.0)]
+    watch_interval: f32,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the nodes and tracks that differ between two anims, without splicing anything
+    Diff {
+        #[arg(short = 'r', long)]
+        reference_anim_file: PathBuf,
+        #[arg(short = 'm', long)]
+        modified_anim_file: PathBuf,
+    },
 }
 
 #[derive(Clone)]
-struct AnimTransformNodeData {
-    name: String,
+struct AnimTrackData {
     buffer: Vec<u8>,
     track: TrackV2,
 }
 
+#[derive(Clone)]
+struct AnimTransformNodeData {
+    /// The name exactly as it was read, which isn't necessarily valid UTF-8.
+    raw_name: SsbhString,
+    /// The name for sorting and messages, with any invalid UTF-8 replaced.
+    name: String,
+    /// Every track of the node, which is usually exactly one Transform track.
+    tracks: Vec<AnimTrackData>,
+}
+
 impl AnimTransformNodeData {
     pub fn from(node: &Node, buffer: &SsbhByteBuffer) -> Self {
-        let track = &node.tracks.elements[0];
-        let start_index = track.data_offset as usize;
-        let end_index = (track.data_offset as u64 + track.data_size) as usize;
-        let buffer_slice = &buffer.elements[start_index..end_index];
         Self {
-            name: String::from(node.name.to_str().unwrap()),
-            buffer: buffer_slice.to_vec(),
-            track: track.clone(),
+            raw_name: node.name.clone(),
+            name: node.name.to_string_lossy(),
+            tracks: node
+                .tracks
+                .elements
+                .iter()
+                .map(|track| AnimTrackData {
+                    buffer: track_data(track, buffer)
+                        .expect("every anim's track ranges are checked when it's read")
+                        .to_vec(),
+                    track: track.clone(),
+                })
+                .collect(),
         }
     }
 }
 
+#[derive(Debug, Default)]
+struct SpliceOptions {
+    /// When set, added bones are decoded and re-encoded with this mapping instead of copied as raw bytes.
+    added_bone_frame_mapping: Option<FrameMapping>,
+    /// Overrides for which modified anim a group or node is taken from, when there are several.
+    source_rules: Vec<SourceRule>,
+    /// When set, every Transform bone decision is reported with its place in the hierarchy.
+    skeleton: Option<Skeleton>,
+    /// Bones that are never added from a modified anim, compared ignoring case since scripts use lowercase names.
+    locked_bones: Vec<String>,
+    /// The collision bones of dumped fighter scripts, which are also locked in the anims of their motion.
+    hitbox_bones: Option<HitboxBones>,
+    /// The fighter of the anims in `hitbox_bones`, when it can't be told from their paths.
+    fighter: Option<String>,
+    node_order: NodeOrder,
+    name_conflicts: NameConflictPolicy,
+    /// When not set, the alignment is measured from each reference anim.
+    buffer_alignment: Option<usize>,
+    deduplicate_tracks: bool,
+    /// When set, each reference anim is also spliced with itself to check that the output layout matches it.
+    round_trip_check: bool,
+}
+
+impl SpliceOptions {
+    fn from_args(args: &Args) -> Result<Self> {
+        let hitbox_bones = match &args.acmd_dump {
+            Some(acmd_dump) => {
+                let hitbox_bones = HitboxBones::from_dump(acmd_dump)?;
+                hitbox_bones.print_summary();
+                Some(hitbox_bones)
+            }
+            None if args.fighter.is_some() => {
+                return Err(anyhow::format_err!(
+                    "--fighter only chooses the fighter of --acmd_dump!"
+                ));
+            }
+            None => None,
+        };
+        if args.deduplicate_tracks {
+            println!("WARNING: --deduplicate_tracks points several tracks at the same data, which vanilla anims aren't known to do. Test the spliced anims in game before sharing them.");
+        }
+        Ok(Self {
+            added_bone_frame_mapping: args.retime_added_bones.then_some(FrameMapping {
+                offset: args.frame_offset,
+                scale: args.frame_scale,
+                trim_start: args.trim_start,
+                trim_end: args.trim_end,
+                looping: args.loop_added_bones,
+            }),
+            source_rules: args.source_rules.clone(),
+            skeleton: args
+                .skeleton_file
+                .as_deref()
+                .map(Skeleton::from_file)
+                .transpose()?,
+            locked_bones: args.locked_bones.clone(),
+            hitbox_bones,
+            fighter: args.fighter.clone(),
+            node_order: args.node_order,
+            name_conflicts: args.name_conflicts,
+            buffer_alignment: args.buffer_alignment,
+            deduplicate_tracks: args.deduplicate_tracks,
+            round_trip_check: args.round_trip_check,
+        })
+    }
+}
+
+impl SpliceOptions {
+    /// The bones locked in one anim, `locked_bones` along with the collision bones of the anim's motion.
+    fn locked_bones_for(
+        &self,
+        reference_anim_path: &Path,
+        modified_anims: &[Option<PathBuf>],
+    ) -> Result<Vec<String>> {
+        let mut locked_bones = self.locked_bones.clone();
+        if let Some(hitbox_bones) = &self.hitbox_bones {
+            let anim_paths = std::iter::once(reference_anim_path)
+                .chain(modified_anims.iter().flatten().map(PathBuf::as_path))
+                .collect::<Vec<_>>();
+            locked_bones.extend(
+                hitbox_bones
+                    .bones_for_anim(self.fighter.as_deref(), &anim_paths)?
+                    .into_iter()
+                    .map(String::from),
+            );
+        }
+        Ok(locked_bones)
+    }
+}
+
 struct AnimGroupWithBuffer<'a> {
     group: &'a Group,
     buffer: &'a SsbhByteBuffer,
@@ -79,37 +302,242 @@ fn get_anim_group_and_buffer_with_fallback<'a>(
     }
 }
 
-fn splice_anim(reference_anim: &PathBuf, modified_anim: &PathBuf) -> Result<Anim> {
-    let reference_anim =
-        ssbh_lib::formats::anim::Anim::from_file(reference_anim).with_context(|| {
-            format!(
-                "could not read reference anim `{}`",
-                &reference_anim.display()
-            )
-        })?;
+struct AnimNodeWithBuffer<'a> {
+    node: &'a Node,
+    buffer: &'a SsbhByteBuffer,
+}
+
+/// Keeps every fallback node, replacing the ones that share a name with a priority node,
+/// and then appends the priority nodes that aren't in the fallback group at all.
+fn merge_anim_group_nodes<'a>(
+    priority_groups: &'a SsbhArray<Group>,
+    priority_buffer: &'a SsbhByteBuffer,
+    fallback_groups: &'a SsbhArray<Group>,
+    fallback_buffer: &'a SsbhByteBuffer,
+    group_type: GroupType,
+) -> Option<Vec<AnimNodeWithBuffer<'a>>> {
+    let priority_nodes: &[Node] = priority_groups
+        .elements
+        .iter()
+        .find(|group_entry| group_entry.group_type == group_type)
+        .map(|group| group.nodes.elements.as_slice())
+        .unwrap_or_default();
+
+    let fallback_nodes: &[Node] = fallback_groups
+        .elements
+        .iter()
+        .find(|group_entry| group_entry.group_type == group_type)
+        .map(|group| group.nodes.elements.as_slice())
+        .unwrap_or_default();
+
+    if priority_nodes.is_empty() && fallback_nodes.is_empty() {
+        return None;
+    }
 
-    let modified_anim =
-        ssbh_lib::formats::anim::Anim::from_file(modified_anim).with_context(|| {
+    let find_priority_node =
+        |name: &SsbhString| priority_nodes.iter().find(|node| node.name == *name);
+
+    let merged_nodes = fallback_nodes
+        .iter()
+        .map(
+            |fallback_node| match find_priority_node(&fallback_node.name) {
+                Some(priority_node) => AnimNodeWithBuffer {
+                    node: priority_node,
+                    buffer: priority_buffer,
+                },
+                None => AnimNodeWithBuffer {
+                    node: fallback_node,
+                    buffer: fallback_buffer,
+                },
+            },
+        )
+        .chain(
+            priority_nodes
+                .iter()
+                .filter(|priority_node| {
+                    !fallback_nodes
+                        .iter()
+                        .any(|fallback_node| fallback_node.name == priority_node.name)
+                })
+                .map(|priority_node| AnimNodeWithBuffer {
+                    node: priority_node,
+                    buffer: priority_buffer,
+                }),
+        )
+        .collect();
+
+    Some(merged_nodes)
+}
+
+/// Reads the modified anim, encoding it with ssbh_data first if it was given as ssbh_data's JSON.
+fn read_modified_anim(modified_anim: &Path) -> Result<Anim> {
+    if !is_json_path(modified_anim) {
+        return archive::read_anim(modified_anim).with_context(|| {
             format!(
                 "could not read modified anim `{}`",
                 &modified_anim.display()
             )
-        })?;
+        });
+    }
 
-    let (reference_groups, reference_buffer) = match &reference_anim {
-        Anim::V20 { groups, buffer, .. } | Anim::V21 { groups, buffer, .. } => (groups, buffer),
-        Anim::V12 { .. } => {
-            return Err(anyhow::format_err!("v12 reference anim not supported!"));
+    let json = archive::read_to_string(modified_anim).with_context(|| {
+        format!(
+            "could not read modified anim JSON `{}`",
+            &modified_anim.display()
+        )
+    })?;
+    let anim_data: AnimData = serde_json::from_str(&json).with_context(|| {
+        format!(
+            "could not parse modified anim JSON `{}`",
+            &modified_anim.display()
+        )
+    })?;
+    Anim::try_from(&anim_data).with_context(|| {
+        format!(
+            "could not encode modified anim JSON `{}`",
+            &modified_anim.display()
+        )
+    })
+}
+
+struct ModifiedSource {
+    anim: Anim,
+    is_json: bool,
+}
+
+struct ModifiedGroupsWithBuffer<'a> {
+    groups: &'a SsbhArray<Group>,
+    buffer: &'a SsbhByteBuffer,
+    is_json: bool,
+}
+
+fn find_group(groups: &SsbhArray<Group>, group_type: GroupType) -> Option<&Group> {
+    groups
+        .elements
+        .iter()
+        .find(|group_entry| group_entry.group_type == group_type)
+}
+
+struct SplicedAnim {
+    anim: Anim,
+    stats: SpliceStats,
+}
+
+/// Splices the reference anim with the modified anims, in order of precedence.
+///
+/// A `None` modified anim keeps its position for the source rules but has nothing to contribute,
+/// which happens in batch mode when only some modified folders have a matching anim.
+fn splice_anim(
+    reference_anim_path: &Path,
+    modified_anims: &[Option<PathBuf>],
+    options: &SpliceOptions,
+) -> Result<SplicedAnim> {
+    let reference_anim = archive::read_anim(reference_anim_path).with_context(|| {
+        format!(
+            "could not read reference anim `{}`",
+            &reference_anim_path.display()
+        )
+    })?;
+    let reference_anim_label = format!("reference anim `{}`", reference_anim_path.display());
+    check_track_ranges(&reference_anim, &reference_anim_label)?;
+    report_transform_node_layouts(&reference_anim, &reference_anim_label);
+    warn_about_reference_name_conflicts(&reference_anim, &reference_anim_label);
+
+    let locked_bones = options.locked_bones_for(reference_anim_path, modified_anims)?;
+
+    for rule in &options.source_rules {
+        if rule.source_index >= modified_anims.len() {
+            return Err(anyhow::format_err!(
+                "The source rule `{rule}` refers to modified anim #{}, but only {} were given!",
+                rule.source_index + 1,
+                modified_anims.len()
+            ));
         }
-    };
+    }
 
-    let (modified_groups, modified_buffer) = match &modified_anim {
-        Anim::V20 { groups, buffer, .. } | Anim::V21 { groups, buffer, .. } => (groups, buffer),
+    let modified_sources = modified_anims
+        .iter()
+        .map(|modified_anim| {
+            modified_anim
+                .as_ref()
+                .map(|path| {
+                    let mut anim = read_modified_anim(path)?;
+                    let anim_label = format!("modified anim `{}`", path.display());
+                    check_track_ranges(&anim, &anim_label)?;
+                    report_transform_node_layouts(&anim, &anim_label);
+                    resolve_name_conflicts(
+                        &mut anim,
+                        &anim_label,
+                        &reference_anim,
+                        options.name_conflicts,
+                    )?;
+                    Ok(ModifiedSource {
+                        anim,
+                        is_json: is_json_path(path),
+                    })
+                })
+                .transpose()
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (reference_groups, reference_buffer, reference_final_frame_index) = match &reference_anim {
+        Anim::V20 {
+            groups,
+            buffer,
+            final_frame_index,
+            ..
+        }
+        | Anim::V21 {
+            groups,
+            buffer,
+            final_frame_index,
+            ..
+        } => (groups, buffer, *final_frame_index),
         Anim::V12 { .. } => {
-            return Err(anyhow::format_err!("v12 modified anim not supported!"));
+            return Err(anyhow::format_err!("v12 reference anim not supported!"));
         }
     };
 
+    let mut modified_groups_and_buffers = Vec::new();
+    for source in &modified_sources {
+        let groups_and_buffer = match source {
+            Some(ModifiedSource {
+                anim:
+                    Anim::V20 {
+                        groups,
+                        buffer,
+                        final_frame_index,
+                        ..
+                    }
+                    | Anim::V21 {
+                        groups,
+                        buffer,
+                        final_frame_index,
+                        ..
+                    },
+                is_json,
+            }) => {
+                if *is_json && *final_frame_index != reference_final_frame_index {
+                    println!("WARNING: the modified anim JSON has a final_frame_index of `{final_frame_index}`, but the reference anim has a final_frame_index of `{reference_final_frame_index}`. The reference's final_frame_index will be used.");
+                }
+                Some(ModifiedGroupsWithBuffer {
+                    groups,
+                    buffer,
+                    is_json: *is_json,
+                })
+            }
+            Some(ModifiedSource {
+                anim: Anim::V12 { .. },
+                ..
+            }) => {
+                return Err(anyhow::format_err!("v12 modified anim not supported!"));
+            }
+            None => None,
+        };
+        modified_groups_and_buffers.push(groups_and_buffer);
+    }
+    let source_count = modified_groups_and_buffers.len();
+
     let reference_transform_group = reference_groups
         .elements
         .iter()
@@ -126,37 +554,173 @@ fn splice_anim(reference_anim: &PathBuf, modified_anim: &PathBuf) -> Result<Anim
         None => Vec::new(),
     };
 
-    let modified_transform_group = modified_groups
-        .elements
-        .iter()
-        .find(|group_entry| group_entry.group_type == GroupType::Transform);
-
-    // Basically the transform data of added bones in the new anim ONLY.
-    let modified_exclusive_transform_nodes_data: Vec<AnimTransformNodeData> =
-        match modified_transform_group {
-            Some(group) => group
-                .nodes
-                .elements
+    // Basically the transform data of added bones in the new anims ONLY, grouped by the modified anim they come from.
+    let mut added_bone_names_by_source: Vec<Vec<SsbhString>> = vec![Vec::new(); source_count];
+    let mut seen_added_bone_names: Vec<SsbhString> = Vec::new();
+    for modified in modified_groups_and_buffers.iter().flatten() {
+        let modified_nodes = find_group(modified.groups, GroupType::Transform)
+            .map(|group| group.nodes.elements.as_slice())
+            .unwrap_or_default();
+        for mod_node in modified_nodes {
+            let name = &mod_node.name;
+            if reference_transform_nodes_data
                 .iter()
-                .filter(|mod_node| {
-                    !reference_transform_nodes_data
-                        .iter()
-                        .any(|ref_node| mod_node.name.to_string_lossy() == ref_node.name)
+                .any(|ref_node| ref_node.raw_name == *name)
+                || seen_added_bone_names.contains(name)
+            {
+                continue;
+            }
+            if locked_bones.iter().any(|locked| {
+                locked
+                    .as_bytes()
+                    .eq_ignore_ascii_case(name.to_string_lossy().as_bytes())
+            }) {
+                let hierarchy = options
+                    .skeleton
+                    .as_ref()
+                    .map(|skeleton| {
+                        format!(
+                            ", hierarchy=`{}`",
+                            skeleton.hierarchy_path(&name.to_string_lossy())
+                        )
+                    })
+                    .unwrap_or_default();
+                println!("LOCKED: `{}` is a locked bone that the reference anim doesn't animate, so it won't be added from a modified anim{hierarchy}.", name.to_string_lossy());
+                seen_added_bone_names.push(name.clone());
+                continue;
+            }
+
+            // A name that isn't valid UTF-8 can't match a node rule, so only group rules apply to it.
+            let source_index = source_order(
+                &options.source_rules,
+                GroupType::Transform,
+                name.to_str(),
+                source_count,
+            )
+            .into_iter()
+            .find(|&i| {
+                modified_groups_and_buffers[i].as_ref().is_some_and(|m| {
+                    find_group(m.groups, GroupType::Transform)
+                        .and_then(|group| find_node(group, name))
+                        .is_some()
                 })
-                .map(|mod_node| AnimTransformNodeData::from(mod_node, modified_buffer))
-                .collect(),
-            None => Vec::new(),
+            })
+            .with_context(|| {
+                format!(
+                    "the added bone `{}` isn't in any modified anim",
+                    name.to_string_lossy()
+                )
+            })?;
+            added_bone_names_by_source[source_index].push(name.clone());
+            seen_added_bone_names.push(name.clone());
+        }
+    }
+
+    if let Some(skeleton) = &options.skeleton {
+        let reference_bone_names = reference_transform_nodes_data
+            .iter()
+            .map(|node_data| node_data.name.clone())
+            .collect::<Vec<_>>();
+        let added_bone_names_by_source = added_bone_names_by_source
+            .iter()
+            .map(|names| {
+                names
+                    .iter()
+                    .map(|name| name.to_string_lossy())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        skeleton::report_splice_hierarchy(
+            skeleton,
+            &reference_bone_names,
+            &added_bone_names_by_source,
+        );
+    }
+
+    let mut modified_exclusive_transform_nodes_data: Vec<AnimTransformNodeData> = Vec::new();
+    for (source_index, added_bone_names) in added_bone_names_by_source.iter().enumerate() {
+        let (Some(source), Some(modified)) = (
+            &modified_sources[source_index],
+            &modified_groups_and_buffers[source_index],
+        ) else {
+            continue;
         };
+        match &options.added_bone_frame_mapping {
+            Some(mapping) => {
+                // ssbh_data decodes names as strings, so a name that isn't valid UTF-8 couldn't be written back as is.
+                let added_bone_names = added_bone_names
+                    .iter()
+                    .map(|name| {
+                        name.to_str().map(String::from).ok_or_else(|| {
+                            anyhow::format_err!(
+                                "The added bone `{}` from modified anim #{} can't be retimed because its name isn't valid UTF-8!",
+                                name.to_string_lossy(),
+                                source_index + 1
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                modified_exclusive_transform_nodes_data.extend(retime::reencode_transform_nodes(
+                    &source.anim,
+                    &added_bone_names,
+                    mapping,
+                    reference_final_frame_index,
+                )?);
+            }
+            None => {
+                for name in added_bone_names {
+                    let mod_node = find_group(modified.groups, GroupType::Transform)
+                        .and_then(|group| find_node(group, name))
+                        .with_context(|| {
+                            format!(
+                                "the added bone `{}` isn't in modified anim #{}",
+                                name.to_string_lossy(),
+                                source_index + 1
+                            )
+                        })?;
+                    modified_exclusive_transform_nodes_data
+                        .push(AnimTransformNodeData::from(mod_node, modified.buffer));
+                }
+            }
+        }
+    }
 
-    let spliced_transform_nodes_data: Vec<AnimTransformNodeData> = reference_transform_nodes_data
+    let mut modified_node_names: Vec<SsbhString> = Vec::new();
+    for node in modified_groups_and_buffers
         .iter()
-        .cloned()
-        .chain(modified_exclusive_transform_nodes_data.iter().cloned())
-        .sorted_by_key(|x| x.name.to_lowercase())
-        .collect::<Vec<_>>();
+        .flatten()
+        .filter_map(|modified| find_group(modified.groups, GroupType::Transform))
+        .flat_map(|group| group.nodes.elements.iter())
+    {
+        if !modified_node_names.contains(&node.name) {
+            modified_node_names.push(node.name.clone());
+        }
+    }
+
+    let mut stats = SpliceStats {
+        reference_bones_kept: reference_transform_nodes_data.len(),
+        bones_added: modified_exclusive_transform_nodes_data.len(),
+        ..Default::default()
+    };
+
+    let spliced_transform_nodes_data = node_order::order_transform_nodes(
+        options.node_order,
+        reference_transform_nodes_data.clone(),
+        modified_exclusive_transform_nodes_data,
+        &modified_node_names,
+    );
 
-    let mut current_offset: u64 = 0;
-    let mut new_buffer = SsbhByteBuffer::new();
+    if let Some((first, second)) = node_order::find_reference_order_mismatch(
+        &reference_transform_nodes_data,
+        &spliced_transform_nodes_data,
+    ) {
+        println!("WARNING: the `{:?}` node order puts the vanilla bone `{first}` before `{second}`, but the reference anim has them the other way around.", options.node_order);
+    }
+
+    let buffer_alignment = options
+        .buffer_alignment
+        .unwrap_or_else(|| layout::measure_alignment(&reference_anim));
+    let mut new_buffer = BufferBuilder::new(buffer_alignment, options.deduplicate_tracks);
     let mut new_groups: SsbhArray<ssbh_lib::formats::anim::Group> = SsbhArray::new();
 
     if !spliced_transform_nodes_data.is_empty() {
@@ -165,61 +729,153 @@ fn splice_anim(reference_anim: &PathBuf, modified_anim: &PathBuf) -> Result<Anim
             nodes: SsbhArray::new(),
         };
         for node_data in &spliced_transform_nodes_data {
-            let new_node = ssbh_lib::formats::anim::Node {
-                name: node_data.name.clone().into(),
-                tracks: SsbhArray::from_vec(vec![TrackV2 {
-                    data_offset: current_offset as u32,
-                    ..node_data.track.clone()
-                }]),
+            let mut new_node = ssbh_lib::formats::anim::Node {
+                name: node_data.raw_name.clone(),
+                tracks: SsbhArray::new(),
             };
+            for track_data in &node_data.tracks {
+                let data_offset = new_buffer.push(&track_data.buffer).with_context(|| {
+                    format!(
+                        "could not add the Transform node `{}` to the spliced anim",
+                        node_data.name
+                    )
+                })?;
+                new_node.tracks.elements.push(TrackV2 {
+                    data_offset,
+                    ..track_data.track.clone()
+                });
+            }
 
-            new_buffer.elements.extend_from_slice(&node_data.buffer);
-            current_offset += node_data.buffer.len() as u64;
             new_transform_group.nodes.elements.push(new_node);
         }
         new_groups.elements.push(new_transform_group);
     }
 
-    let spliced_vis_group_and_buf = get_anim_group_and_buffer_with_fallback(
-        modified_groups,
-        modified_buffer,
-        reference_groups,
-        reference_buffer,
-        GroupType::Visibility,
-    );
+    for group_type in [GroupType::Visibility, GroupType::Material] {
+        // The group comes from the most preferred modified anim that has it.
+        let group_order = source_order(&options.source_rules, group_type, None, source_count);
+        let base_source_index = group_order.iter().copied().find(|&i| {
+            modified_groups_and_buffers[i]
+                .as_ref()
+                .is_some_and(|modified| find_group(modified.groups, group_type).is_some())
+        });
+        let base_source = base_source_index
+            .and_then(|i| modified_groups_and_buffers[i].as_ref())
+            .or_else(|| modified_groups_and_buffers.iter().flatten().next());
 
-    let spliced_mat_group_and_buf = get_anim_group_and_buffer_with_fallback(
-        modified_groups,
-        modified_buffer,
-        reference_groups,
-        reference_buffer,
-        GroupType::Material,
-    );
+        // A JSON file only describes the tracks it adds or replaces, so its nodes are merged into the reference's group.
+        // A modified anim replaces the reference's group entirely.
+        let spliced_nodes = match base_source {
+            Some(modified) if modified.is_json => merge_anim_group_nodes(
+                modified.groups,
+                modified.buffer,
+                reference_groups,
+                reference_buffer,
+                group_type,
+            ),
+            _ => get_anim_group_and_buffer_with_fallback(
+                base_source.map_or(reference_groups, |modified| modified.groups),
+                base_source.map_or(reference_buffer, |modified| modified.buffer),
+                reference_groups,
+                reference_buffer,
+                group_type,
+            )
+            .map(|spliced_group| {
+                spliced_group
+                    .group
+                    .nodes
+                    .elements
+                    .iter()
+                    .map(|node| AnimNodeWithBuffer {
+                        node,
+                        buffer: spliced_group.buffer,
+                    })
+                    .collect::<Vec<_>>()
+            }),
+        };
+
+        // Node rules then pull individual nodes from other modified anims.
+        let mut spliced_nodes = spliced_nodes;
+        let mut node_rule_applied = false;
+        for rule in &options.source_rules {
+            let Some(node_name) = rule.node_name.as_deref() else {
+                continue;
+            };
+            if rule.group_type != group_type {
+                continue;
+            }
+            let Some(modified) = &modified_groups_and_buffers[rule.source_index] else {
+                continue;
+            };
+            let node_name = SsbhString::from(node_name);
+            let Some(node) = find_group(modified.groups, group_type)
+                .and_then(|group| find_node(group, &node_name))
+            else {
+                println!("WARNING: the source rule `{rule}` refers to a node that isn't in that modified anim, so it will be ignored.");
+                continue;
+            };
+            let new_node = AnimNodeWithBuffer {
+                node,
+                buffer: modified.buffer,
+            };
+            node_rule_applied = true;
+            let nodes = spliced_nodes.get_or_insert_with(Vec::new);
+            match nodes
+                .iter_mut()
+                .find(|spliced_node| spliced_node.node.name == node_name)
+            {
+                Some(spliced_node) => *spliced_node = new_node,
+                None => nodes.push(new_node),
+            }
+        }
+
+        let Some(spliced_nodes) = spliced_nodes else {
+            continue;
+        };
+
+        let mut source = match base_source_index {
+            Some(i)
+                if modified_groups_and_buffers[i]
+                    .as_ref()
+                    .is_some_and(|m| m.is_json) =>
+            {
+                format!("modified anim #{} merged into the reference anim", i + 1)
+            }
+            Some(i) => format!("modified anim #{}", i + 1),
+            None => "reference anim".to_string(),
+        };
+        if node_rule_applied {
+            source.push_str(", with node rules");
+        }
+        match group_type {
+            GroupType::Visibility => stats.visibility_source = Some(source),
+            _ => stats.material_source = Some(source),
+        }
 
-    for spliced_group in vec![spliced_vis_group_and_buf, spliced_mat_group_and_buf]
-        .into_iter()
-        .flatten()
-    {
         let mut new_group = Group {
-            group_type: spliced_group.group.group_type,
+            group_type,
             nodes: SsbhArray::new(),
         };
-        for old_node in &spliced_group.group.nodes.elements {
+        for spliced_node in &spliced_nodes {
+            let old_node = spliced_node.node;
             let mut new_node = Node {
                 name: old_node.name.clone(),
                 tracks: SsbhArray::new(),
             };
             for old_track in &old_node.tracks.elements {
+                let slice = track_data(old_track, spliced_node.buffer)
+                    .expect("every anim's track ranges are checked when it's read");
+                let data_offset = new_buffer.push(slice).with_context(|| {
+                    format!(
+                        "could not add the {group_type:?} node `{}`'s track `{}` to the spliced anim",
+                        old_node.name.to_string_lossy(),
+                        old_track.name.to_string_lossy()
+                    )
+                })?;
                 let new_track = TrackV2 {
-                    data_offset: current_offset as u32,
+                    data_offset,
                     ..old_track.clone()
                 };
-                let start_index = old_track.data_offset as usize;
-                let end_index = (old_track.data_offset as u64 + old_track.data_size) as usize;
-                let old_buffer = spliced_group.buffer;
-                let slice = &old_buffer.elements[start_index..end_index];
-                new_buffer.elements.extend_from_slice(slice);
-                current_offset += slice.len() as u64;
                 new_node.tracks.elements.push(new_track);
             }
             new_group.nodes.elements.push(new_node);
@@ -227,21 +883,29 @@ fn splice_anim(reference_anim: &PathBuf, modified_anim: &PathBuf) -> Result<Anim
         new_groups.elements.push(new_group);
     }
 
-    match reference_anim {
+    check_spliced_name_conflicts(
+        &new_groups.elements,
+        &std::iter::once(&reference_anim)
+            .chain(modified_sources.iter().flatten().map(|source| &source.anim))
+            .collect::<Vec<_>>(),
+        options.name_conflicts,
+    )?;
+
+    let anim = match reference_anim {
         Anim::V20 {
             final_frame_index,
             unk1,
             unk2,
             name,
             ..
-        } => Ok(Anim::V20 {
+        } => Anim::V20 {
             final_frame_index,
             unk1,
             unk2,
             name,
             groups: new_groups,
-            buffer: new_buffer,
-        }),
+            buffer: new_buffer.into_buffer(),
+        },
 
         Anim::V21 {
             final_frame_index,
@@ -250,104 +914,346 @@ fn splice_anim(reference_anim: &PathBuf, modified_anim: &PathBuf) -> Result<Anim
             name,
             unk_data,
             ..
-        } => Ok(Anim::V21 {
+        } => Anim::V21 {
             final_frame_index,
             unk1,
             unk2,
             name,
             groups: new_groups,
-            buffer: new_buffer,
+            buffer: new_buffer.into_buffer(),
             unk_data,
-        }),
+        },
 
-        _ => Err(anyhow::format_err!(
-            "Got an unsupported reference anim but this code should have never been reached "
-        )),
-    }
+        _ => {
+            return Err(anyhow::format_err!(
+                "Got an unsupported reference anim but this code should have never been reached "
+            ))
+        }
+    };
+    Ok(SplicedAnim { anim, stats })
+}
+
+/// Copies a modified anim that has no vanilla counterpart to the output folder, after checking that it's a readable anim.
+///
+/// The first modified folder that has the anim wins. A JSON file is encoded and written as a `.nuanmb`.
+fn copy_unmatched_anim(
+    modified_anim_paths: &[Option<PathBuf>],
+    batch_output: &mut BatchOutput,
+    backup: bool,
+) -> Result<(PathBuf, PathBuf)> {
+    let source = modified_anim_paths
+        .iter()
+        .flatten()
+        .next()
+        .context("the anim isn't in any modified folder")?;
+    let anim = read_modified_anim(source)?;
+    let anim_label = format!("modified anim `{}`", source.display());
+    check_track_ranges(&anim, &anim_label)?;
+    report_transform_node_layouts(&anim, &anim_label);
+
+    let output_entry = zip_entry_dir(source)
+        .join(source.file_stem().unwrap_or_default())
+        .with_extension("nuanmb");
+    let bytes = if is_json_path(source) {
+        archive::anim_bytes(&anim)?
+    } else {
+        archive::read(source)?
+    };
+    let output = batch_output
+        .write(&output_entry, bytes, backup)
+        .with_context(|| {
+            format!(
+                "could not copy the anim to `{}`",
+                batch_output.path(&output_entry).display()
+            )
+        })?;
+    Ok((source.clone(), output))
+}
+
+/// Batch mode settings that don't change how each anim is spliced.
+#[derive(Clone, Default)]
+struct BatchOptions {
+    /// When set, the batch report is also written to this JSON file.
+    report_file: Option<PathBuf>,
+    /// Skip the anims whose inputs haven't changed since the last run.
+    incremental: bool,
+    /// Copy modified anims without a vanilla anim to the output folder instead of skipping them.
+    copy_unmatched: bool,
+    /// Allow the output folder to be an input folder, backing up the overwritten files.
+    in_place: bool,
+}
+
+/// Finds a modified folder with both a JSON file and a `.nuanmb` for the same anim, since neither should silently win.
+fn find_json_and_nuanmb<'a>(
+    paths: &'a [PathBuf],
+    stem: &OsStr,
+) -> Option<(&'a PathBuf, &'a PathBuf)> {
+    let mut with_stem = paths.iter().filter(|p| p.file_stem() == Some(stem));
+    let json = with_stem.clone().find(|p| is_json_path(p))?;
+    let nuanmb = with_stem.find(|p| is_nuanmb_path(p))?;
+    Some((json, nuanmb))
 }
 
 fn do_batch_mode(
-    batch_reference_dir: &PathBuf,
-    batch_modified_dir: &PathBuf,
+    batch_reference_dir: &Path,
+    batch_modified_dirs: &[PathBuf],
     batch_output_dir: &Path,
-) -> Result<()> {
-    let reference_anim_paths = fs::read_dir(batch_reference_dir)
-        .unwrap()
-        .filter_map(|dir_entry| dir_entry.ok())
-        .map(|dir_entry| dir_entry.path())
-        .filter(|path| path.extension().unwrap().eq("nuanmb"))
-        .collect::<Vec<_>>();
+    batch_options: &BatchOptions,
+    options: &SpliceOptions,
+) -> Result<BatchReport> {
+    let mut inputs = vec![("reference folder", batch_reference_dir)];
+    inputs.extend(
+        batch_modified_dirs
+            .iter()
+            .map(|dir| ("modified folder", dir.as_path())),
+    );
+    check_output_path(batch_output_dir, &inputs, batch_options.in_place)?;
+    if batch_options.incremental && is_zip_path(batch_output_dir) {
+        return Err(anyhow::format_err!(
+            "--incremental and --watch need an output folder, since an output zip file is always written from scratch!"
+        ));
+    }
+
+    let reference_anim_paths = read_anim_dir(batch_reference_dir, is_nuanmb_path)
+        .context("could not read the reference folder")?;
+
+    let modified_anim_paths_by_dir = batch_modified_dirs
+        .iter()
+        .map(|batch_modified_dir| {
+            read_anim_dir(batch_modified_dir, |path| {
+                is_nuanmb_path(path) || is_json_path(path)
+            })
+            .context("could not read a modified folder")
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-    let modified_anim_paths = fs::read_dir(batch_modified_dir)
-        .unwrap()
-        .filter_map(|dir_entry| dir_entry.ok())
-        .map(|dir_entry| dir_entry.path())
-        .filter(|path| path.extension().unwrap().eq("nuanmb"))
+    // Anims are matched by name, so a zip file with the same anim in several folders, like for several costumes,
+    // can only contribute one of them.
+    for modified_anim_paths in &modified_anim_paths_by_dir {
+        for duplicate in modified_anim_paths
+            .iter()
+            .duplicates_by(|path| (path.file_stem(), is_json_path(path)))
+        {
+            println!("WARNING: {duplicate:?} has the same name as another modified anim in its folder, so it will be ignored.");
+        }
+    }
+
+    let mut batch_output = BatchOutput::new(batch_output_dir)?;
+
+    // Compare stems so that `a00wait1.json` still matches `a00wait1.nuanmb`.
+    let modified_anim_stems = modified_anim_paths_by_dir
+        .iter()
+        .flatten()
+        .filter_map(|path| path.file_stem())
+        .unique()
         .collect::<Vec<_>>();
 
-    for modified_anim_path in modified_anim_paths {
+    let mut build_cache = batch_options
+        .incremental
+        .then(|| BuildCache::load(batch_output_dir));
+    let mut report = BatchReport::default();
+    for modified_anim_stem in modified_anim_stems {
+        let anim = modified_anim_stem.to_string_lossy().to_string();
+        let modified_anim_paths = modified_anim_paths_by_dir
+            .iter()
+            .map(|paths| {
+                paths
+                    .iter()
+                    .find(|p| p.file_stem() == Some(modified_anim_stem))
+                    .cloned()
+            })
+            .collect::<Vec<_>>();
+
+        if let Some((json, nuanmb)) = modified_anim_paths_by_dir
+            .iter()
+            .find_map(|paths| find_json_and_nuanmb(paths, modified_anim_stem))
+        {
+            let e = anyhow::format_err!("both {json:?} and {nuanmb:?} are in the same modified folder, so it's unclear which one to splice. Remove one of them.");
+            println!("An error {e} happened matching {modified_anim_stem:?}, so no spliced anim will be outputted.");
+            report.failed.push(FailedEntry {
+                anim,
+                error: format!("{e:#}"),
+            });
+            continue;
+        }
+
         let matching_vanilla_anim_path: PathBuf = match reference_anim_paths
             .iter()
-            .find(|&p| p.file_name() == modified_anim_path.file_name())
+            .find(|&p| p.file_stem() == Some(modified_anim_stem))
         {
             Some(path) => path.clone(),
+            None if batch_options.copy_unmatched => {
+                match copy_unmatched_anim(
+                    &modified_anim_paths,
+                    &mut batch_output,
+                    batch_options.in_place,
+                ) {
+                    Ok((source, output)) => {
+                        println!("COPIED: {modified_anim_stem:?} has no vanilla anim, so {source:?} was copied to {output:?} unchanged.");
+                        report.copied.push(CopiedEntry {
+                            anim,
+                            source,
+                            output,
+                        });
+                    }
+                    Err(e) => {
+                        println!("An error {e} happened copying {modified_anim_stem:?}, which has no vanilla anim, so it will not be outputted.");
+                        report.failed.push(FailedEntry {
+                            anim,
+                            error: format!("{e:#}"),
+                        });
+                    }
+                }
+                continue;
+            }
             None => {
                 println!(
-                    "Skipping modified file {modified_anim_path:?}, no vanilla anim was found!"
+                    "Skipping modified file {modified_anim_stem:?}, no vanilla anim was found!"
                 );
+                report.skipped.push(SkippedEntry {
+                    anim,
+                    reason: "no vanilla anim was found".to_string(),
+                });
                 continue;
             }
         };
 
-        let new_anim: Anim = match splice_anim(&matching_vanilla_anim_path, &modified_anim_path) {
-            Ok(anim) => anim,
+        // Keep the folders of a modified anim from a zip file, so a zip file can be spliced into the same layout.
+        let first_modified_anim_path = modified_anim_paths
+            .iter()
+            .flatten()
+            .next()
+            .context("the anim isn't in any modified folder")?;
+        let output_entry = zip_entry_dir(first_modified_anim_path)
+            .join(matching_vanilla_anim_path.file_name().unwrap());
+        let output_file_path = batch_output.path(&output_entry);
+
+        // Inputs that can't be hashed just aren't cached, splicing them will report the actual problem.
+        let cache_inputs = build_cache.as_ref().and_then(|_| {
+            CacheInputs::new(&matching_vanilla_anim_path, &modified_anim_paths, options).ok()
+        });
+        if let (Some(build_cache), Some(cache_inputs)) = (&build_cache, &cache_inputs) {
+            if build_cache.is_up_to_date(&output_file_path, cache_inputs) {
+                println!("UNCHANGED: Skipping {modified_anim_stem:?}, since its inputs and output haven't changed since the last run.");
+                report.unchanged.push(UnchangedEntry {
+                    anim,
+                    output: output_file_path,
+                });
+                continue;
+            }
+        }
+
+        if options.round_trip_check {
+            round_trip::report_round_trip(&matching_vanilla_anim_path, options);
+        }
+
+        let spliced_anim = match splice_anim(
+            &matching_vanilla_anim_path,
+            &modified_anim_paths,
+            options,
+        ) {
+            Ok(spliced_anim) => spliced_anim,
             Err(e) => {
-                println!("An error {e} happened splicing {modified_anim_path:?} with {matching_vanilla_anim_path:?}, so no spliced anim will be outputted.");
+                println!("An error {e} happened splicing {modified_anim_stem:?} with {matching_vanilla_anim_path:?}, so no spliced anim will be outputted.");
+                report.failed.push(FailedEntry {
+                    anim,
+                    error: format!("{e:#}"),
+                });
+                if let Some(build_cache) = &mut build_cache {
+                    build_cache.remove(&output_file_path);
+                }
                 continue;
             }
         };
 
-        let output_file_path = batch_output_dir.join(modified_anim_path.file_name().unwrap());
-        new_anim.write_to_file(&output_file_path).with_context(|| {
-            format!(
-                "could not output the new anim to the output path `{}`",
-                &output_file_path.display()
-            )
-        })?;
+        let write_result = archive::anim_bytes(&spliced_anim.anim)
+            .and_then(|bytes| batch_output.write(&output_entry, bytes, batch_options.in_place));
+        if let Err(e) = write_result {
+            println!("An error {e} happened writing the spliced anim to {output_file_path:?}, so it was skipped.");
+            report.failed.push(FailedEntry {
+                anim,
+                error: format!("could not write `{}`: {e}", output_file_path.display()),
+            });
+            if let Some(build_cache) = &mut build_cache {
+                build_cache.remove(&output_file_path);
+            }
+            continue;
+        }
+        if let (Some(build_cache), Some(cache_inputs)) = (&mut build_cache, cache_inputs) {
+            if let Err(e) = build_cache.insert(&output_file_path, cache_inputs) {
+                println!("WARNING: could not add {output_file_path:?} to the build cache, so it will be spliced again next time. error=`{e}`");
+            }
+        }
+        report.spliced.push(SplicedEntry {
+            anim,
+            reference: matching_vanilla_anim_path,
+            output: output_file_path,
+            stats: spliced_anim.stats,
+        });
+    }
+
+    if let Some(build_cache) = &build_cache {
+        build_cache.save(batch_output_dir)?;
     }
-    Ok(())
+    batch_output.finish(batch_options.in_place)?;
+
+    report.print_summary();
+    if let Some(report_file) = &batch_options.report_file {
+        report.write_json(report_file)?;
+    }
+    Ok(report)
 }
 
 fn do_single_mode(
-    reference_anim: &PathBuf,
-    modified_anim: &PathBuf,
-    output_anim: &PathBuf,
+    reference_anim: &Path,
+    modified_anims: &[PathBuf],
+    output_anim: &Path,
+    in_place: bool,
+    options: &SpliceOptions,
 ) -> Result<()> {
-    let new_anim = splice_anim(reference_anim, modified_anim);
-    new_anim?.write_to_file(output_anim).with_context(|| {
+    let mut inputs = vec![("reference anim", reference_anim)];
+    inputs.extend(
+        modified_anims
+            .iter()
+            .map(|path| ("modified anim", path.as_path())),
+    );
+    check_output_path(output_anim, &inputs, in_place)?;
+
+    if options.round_trip_check {
+        round_trip::report_round_trip(reference_anim, options);
+    }
+
+    let modified_anims = modified_anims.iter().cloned().map(Some).collect::<Vec<_>>();
+    let new_anim = splice_anim(reference_anim, &modified_anims, options)?;
+    write_output(output_anim, in_place, |path| {
+        Ok(new_anim.anim.write_to_file(path)?)
+    })
+    .with_context(|| {
         format!(
             "could not output the new anim to the output path `{}`",
             &output_anim.display()
         )
-    })?;
-    Ok(())
+    })
 }
 
 #[derive(PartialEq)]
 enum Mode {
     Single,
     Batch,
+    Command,
     None,
 }
 
 fn get_mode(args: &Args) -> Mode {
-    if args.batch_reference_folder.is_some()
-        || args.batch_modified_folder.is_some()
+    if args.command.is_some() {
+        Mode::Command
+    } else if args.batch_reference_folder.is_some()
+        || !args.batch_modified_folder.is_empty()
         || args.batch_output_folder.is_some()
     {
         Mode::Batch
     } else if args.reference_anim_file.is_some()
-        || args.modified_anim_file.is_some()
+        || !args.modified_anim_file.is_empty()
         || args.output_file.is_some()
     {
         Mode::Single
@@ -361,32 +1267,93 @@ fn main() -> Result<()> {
     let args = Args::parse();
 
     let mode = get_mode(&args);
+    let options = SpliceOptions::from_args(&args)?;
+    if args.watch && mode != Mode::Batch {
+        return Err(anyhow::format_err!(
+            "--watch only works in batch mode, since it watches the batch folders for changes!"
+        ));
+    }
 
     let result = match mode {
         Mode::Batch => {
             let batch_reference_dir = args
                 .batch_reference_folder
-                .expect("Batch mode specified, but the reference folder was not given!");
-            let batch_modified_dir = args
-                .batch_modified_folder
-                .expect("Batch mode specified, but modified folder is missing!");
+                .context("Batch mode specified, but the reference folder was not given!")?;
+            let batch_modified_dirs = args.batch_modified_folder;
+            if batch_modified_dirs.is_empty() {
+                return Err(anyhow::format_err!(
+                    "Batch mode specified, but modified folder is missing!"
+                ));
+            }
             let batch_output_dir = args
                 .batch_output_folder
-                .expect("Batch mode specified, but the output folder is missing!");
-            do_batch_mode(&batch_reference_dir, &batch_modified_dir, &batch_output_dir)
+                .context("Batch mode specified, but the output folder is missing!")?;
+            let batch_options = BatchOptions {
+                report_file: args.batch_report_file,
+                incremental: args.incremental,
+                copy_unmatched: args.copy_unmatched,
+                in_place: args.in_place,
+            };
+            if args.watch {
+                if args.in_place {
+                    return Err(anyhow::format_err!(
+                        "--watch can't be used with --in_place, since every spliced anim would be seen as a new change!"
+                    ));
+                }
+                watch::watch(
+                    &batch_reference_dir,
+                    &batch_modified_dirs,
+                    &batch_output_dir,
+                    &batch_options,
+                    Duration::try_from_secs_f32(args.watch_interval).map_err(|_| {
+                        anyhow::format_err!(
+                            "`{}` is not a valid --watch_interval",
+                            args.watch_interval
+                        )
+                    })?,
+                    &options,
+                )
+            } else {
+                do_batch_mode(
+                    &batch_reference_dir,
+                    &batch_modified_dirs,
+                    &batch_output_dir,
+                    &batch_options,
+                    &options,
+                )
+                .map(|_| ())
+            }
         }
         Mode::Single => {
             let reference_anim_path = args
                 .reference_anim_file
-                .expect("Batch mode was not specified, but a reference anim was not given!");
-            let modified_anim_path = args
-                .modified_anim_file
-                .expect("Batch mode was not specified, but a modified anim was not given!");
-            let output_file_path = args
-                .output_file
-                .expect("Batch mode was not specified, but the output file path was not provided!");
-            do_single_mode(&reference_anim_path, &modified_anim_path, &output_file_path)
+                .context("Batch mode was not specified, but a reference anim was not given!")?;
+            let modified_anim_paths = args.modified_anim_file;
+            if modified_anim_paths.is_empty() {
+                return Err(anyhow::format_err!(
+                    "Batch mode was not specified, but a modified anim was not given!"
+                ));
+            }
+            let output_file_path = args.output_file.context(
+                "Batch mode was not specified, but the output file path was not provided!",
+            )?;
+            do_single_mode(
+                &reference_anim_path,
+                &modified_anim_paths,
+                &output_file_path,
+                args.in_place,
+                &options,
+            )
         }
+        Mode::Command => match args
+            .command
+            .context("Command mode specified, but no command was given!")?
+        {
+            Command::Diff {
+                reference_anim_file,
+                modified_anim_file,
+            } => diff::do_diff_mode(&reference_anim_file, &modified_anim_file),
+        },
         Mode::None => Err(anyhow::format_err!(
             "No arguments passed in! Please run with -h or --help for help."
         )),
@@ -0,0 +1,287 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use ssbh_lib::formats::anim::Group;
+use ssbh_lib::{prelude::*, SsbhString};
+
+/// What to do when a group has several nodes with the same name, ignoring case.
+///
+/// Nodes are matched by their exact name, but sorted case-insensitively, so `ArmR` and `armr`
+/// in the same group would be spliced in an arbitrary order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize)]
+pub enum NameConflictPolicy {
+    /// Refuse to splice the anim
+    #[default]
+    Error,
+    /// Warn, but splice every node anyway
+    KeepAll,
+    /// Warn, and only splice the first of the conflicting nodes
+    First,
+    /// Warn, and only splice the last of the conflicting nodes
+    Last,
+}
+
+fn anim_groups(anim: &Anim) -> &[Group] {
+    match anim {
+        Anim::V20 { groups, .. } | Anim::V21 { groups, .. } => &groups.elements,
+        Anim::V12 { .. } => &[],
+    }
+}
+
+/// The indices of the names that share a lowercased name with another, in the order the names first appear.
+///
+/// A name that isn't valid UTF-8 can't be lowercased, so it only conflicts with the exact same bytes.
+fn conflicting_indices(raw_names: &[&SsbhString]) -> Vec<Vec<usize>> {
+    let mut indices_by_key: Vec<(Result<String, &SsbhString>, Vec<usize>)> = Vec::new();
+    for (index, raw_name) in raw_names.iter().enumerate() {
+        let key = raw_name.to_str().map(str::to_lowercase).ok_or(*raw_name);
+        match indices_by_key.iter_mut().find(|(other, _)| *other == key) {
+            Some((_, indices)) => indices.push(index),
+            None => indices_by_key.push((key, vec![index])),
+        }
+    }
+    indices_by_key
+        .into_iter()
+        .map(|(_, indices)| indices)
+        .filter(|indices| indices.len() > 1)
+        .collect()
+}
+
+fn format_names(raw_names: &[&SsbhString], indices: &[usize]) -> String {
+    indices
+        .iter()
+        .map(|&i| format!("`{}`", raw_names[i].to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Warns about duplicate or case-colliding node names in the reference anim, whose nodes are always kept as they are.
+pub fn warn_about_reference_name_conflicts(reference: &Anim, reference_label: &str) {
+    for group in anim_groups(reference) {
+        let raw_names = group
+            .nodes
+            .elements
+            .iter()
+            .map(|node| &node.name)
+            .collect::<Vec<_>>();
+        for indices in conflicting_indices(&raw_names) {
+            println!(
+                "WARNING: the {reference_label} has conflicting {:?} nodes {}. They're kept like in the reference anim.",
+                group.group_type,
+                format_names(&raw_names, &indices)
+            );
+        }
+    }
+}
+
+/// Checks every group of the modified anim for duplicate or case-colliding node names and resolves them according to `policy`.
+///
+/// Conflicts between nodes that are all in the reference anim too came with the reference anim, so they're left alone.
+pub fn resolve_name_conflicts(
+    anim: &mut Anim,
+    anim_label: &str,
+    reference: &Anim,
+    policy: NameConflictPolicy,
+) -> Result<()> {
+    let groups = match anim {
+        Anim::V20 { groups, .. } | Anim::V21 { groups, .. } => groups,
+        Anim::V12 { .. } => return Ok(()),
+    };
+
+    for group in &mut groups.elements {
+        let raw_names = group
+            .nodes
+            .elements
+            .iter()
+            .map(|node| &node.name)
+            .collect::<Vec<_>>();
+
+        for raw_name in &raw_names {
+            if raw_name.to_str().is_none() {
+                println!("WARNING: the {anim_label} has a {:?} node whose name isn't valid UTF-8, shown as `{}`. Its original bytes will be kept.", group.group_type, raw_name.to_string_lossy());
+            }
+        }
+
+        let reference_names = anim_groups(reference)
+            .iter()
+            .filter(|reference_group| reference_group.group_type == group.group_type)
+            .flat_map(|reference_group| &reference_group.nodes.elements)
+            .map(|node| &node.name)
+            .collect::<Vec<_>>();
+
+        let mut keep = vec![true; raw_names.len()];
+        for indices in conflicting_indices(&raw_names) {
+            if indices
+                .iter()
+                .all(|&i| reference_names.contains(&raw_names[i]))
+            {
+                continue;
+            }
+            let conflicting_names = format_names(&raw_names, &indices);
+            let kind = if indices
+                .iter()
+                .all(|&i| raw_names[i] == raw_names[indices[0]])
+            {
+                "duplicate"
+            } else {
+                "case-colliding"
+            };
+            let group_type = group.group_type;
+
+            match policy {
+                NameConflictPolicy::Error => {
+                    return Err(anyhow::format_err!(
+                        "The {anim_label} has {kind} {group_type:?} nodes {conflicting_names}! Use --name_conflicts to choose how to resolve them."
+                    ));
+                }
+                NameConflictPolicy::KeepAll => {
+                    println!("WARNING: the {anim_label} has {kind} {group_type:?} nodes {conflicting_names}. All of them will be spliced.");
+                }
+                NameConflictPolicy::First => {
+                    println!("WARNING: the {anim_label} has {kind} {group_type:?} nodes {conflicting_names}. Only the first will be spliced.");
+                    for &i in &indices[1..] {
+                        keep[i] = false;
+                    }
+                }
+                NameConflictPolicy::Last => {
+                    println!("WARNING: the {anim_label} has {kind} {group_type:?} nodes {conflicting_names}. Only the last will be spliced.");
+                    for &i in &indices[..indices.len() - 1] {
+                        keep[i] = false;
+                    }
+                }
+            }
+        }
+
+        let mut keep = keep.into_iter();
+        group.nodes.elements.retain(|_| keep.next().unwrap_or(true));
+    }
+
+    Ok(())
+}
+
+/// Checks the groups of the spliced anim for names that only collide across the anims they came from,
+/// like a reference `ArmR` and a modified `armr`.
+///
+/// Conflicts within one anim were already kept or resolved by [resolve_name_conflicts]. Nodes from different anims
+/// can't be dropped in favor of each other, so anything but [NameConflictPolicy::Error] keeps all of them with a warning.
+pub fn check_spliced_name_conflicts(
+    spliced_groups: &[Group],
+    input_anims: &[&Anim],
+    policy: NameConflictPolicy,
+) -> Result<()> {
+    for group in spliced_groups {
+        let raw_names = group
+            .nodes
+            .elements
+            .iter()
+            .map(|node| &node.name)
+            .collect::<Vec<_>>();
+        for indices in conflicting_indices(&raw_names) {
+            let is_within_one_anim = input_anims.iter().any(|anim| {
+                let names = anim_groups(anim)
+                    .iter()
+                    .filter(|input_group| input_group.group_type == group.group_type)
+                    .flat_map(|input_group| &input_group.nodes.elements)
+                    .map(|node| &node.name)
+                    .collect::<Vec<_>>();
+                indices.iter().all(|&i| names.contains(&raw_names[i]))
+            });
+            if is_within_one_anim {
+                continue;
+            }
+
+            let group_type = group.group_type;
+            let conflicting_names = format_names(&raw_names, &indices);
+            match policy {
+                NameConflictPolicy::Error => {
+                    return Err(anyhow::format_err!(
+                        "The spliced anim has {group_type:?} nodes {conflicting_names} from different anims that collide by case! Use --name_conflicts to choose how to resolve them."
+                    ));
+                }
+                _ => {
+                    println!("WARNING: the spliced anim has {group_type:?} nodes {conflicting_names} from different anims that collide by case. All of them will be spliced.");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssbh_data::anim_data::GroupType;
+    use wifisafe_common::test_anims::*;
+
+    fn resolve(names: &[&str], policy: NameConflictPolicy) -> Result<Vec<(String, bool)>> {
+        let modified = anim_data(
+            1,
+            vec![group(
+                GroupType::Visibility,
+                names
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| visibility_node(name, i % 2 == 0))
+                    .collect(),
+            )],
+        );
+        let mut anim = Anim::try_from(&modified).unwrap();
+        let reference = Anim::try_from(&anim_data(1, Vec::new())).unwrap();
+        resolve_name_conflicts(&mut anim, "test anim", &reference, policy)?;
+        Ok(AnimData::try_from(&anim).unwrap().groups[0]
+            .nodes
+            .iter()
+            .map(|node| {
+                let visible = node.tracks[0].values
+                    == ssbh_data::anim_data::TrackValues::Boolean(vec![true; FRAME_COUNT]);
+                (node.name.clone(), visible)
+            })
+            .collect())
+    }
+
+    fn owned(nodes: &[(&str, bool)]) -> Vec<(String, bool)> {
+        nodes
+            .iter()
+            .map(|(name, visible)| (name.to_string(), *visible))
+            .collect()
+    }
+
+    #[test]
+    fn distinct_names_are_kept() {
+        for policy in [
+            NameConflictPolicy::Error,
+            NameConflictPolicy::KeepAll,
+            NameConflictPolicy::First,
+            NameConflictPolicy::Last,
+        ] {
+            assert_eq!(
+                resolve(&["EyeL", "EyeR"], policy).unwrap(),
+                owned(&[("EyeL", true), ("EyeR", false)])
+            );
+        }
+    }
+
+    #[test]
+    fn conflicts_are_an_error_by_default() {
+        assert!(resolve(&["EyeL", "EyeL"], NameConflictPolicy::Error).is_err());
+        assert!(resolve(&["EyeL", "eyel"], NameConflictPolicy::Error).is_err());
+    }
+
+    #[test]
+    fn conflicts_are_resolved_by_the_policy() {
+        let names = ["EyeL", "EyeR", "eyel"];
+        assert_eq!(
+            resolve(&names, NameConflictPolicy::KeepAll).unwrap(),
+            owned(&[("EyeL", true), ("EyeR", false), ("eyel", true)])
+        );
+        assert_eq!(
+            resolve(&names, NameConflictPolicy::First).unwrap(),
+            owned(&[("EyeL", true), ("EyeR", false)])
+        );
+        assert_eq!(
+            resolve(&names, NameConflictPolicy::Last).unwrap(),
+            owned(&[("EyeR", false), ("eyel", true)])
+        );
+    }
+}
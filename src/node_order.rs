@@ -0,0 +1,203 @@
+use clap::ValueEnum;
+use itertools::Itertools;
+use serde::Serialize;
+use ssbh_lib::SsbhString;
+
+use crate::AnimTransformNodeData;
+
+/// How the Transform nodes of the spliced anim are ordered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize)]
+pub enum NodeOrder {
+    /// Sort every node by its lowercased name
+    #[default]
+    Lowercase,
+    /// Sort every node by the raw bytes of its name, like a case-sensitive `strcmp`.
+    /// This is a guess at how the game's exporter sorts, it hasn't been checked against vanilla anims
+    Ordinal,
+    /// Keep the reference anim's order and append the added bones at the end
+    Reference,
+    /// Keep the reference anim's order and insert each added bone after the node it follows in the modified anim
+    ReferenceInsert,
+    /// Use the modified anims' order, with any reference bones they're missing appended at the end
+    Modified,
+}
+
+/// Orders the spliced Transform nodes according to `node_order`.
+///
+/// `modified_node_names` is every Transform node name in the modified anims, in the order they first appear.
+/// Names are matched by their exact bytes.
+pub fn order_transform_nodes(
+    node_order: NodeOrder,
+    reference_nodes: Vec<AnimTransformNodeData>,
+    added_nodes: Vec<AnimTransformNodeData>,
+    modified_node_names: &[SsbhString],
+) -> Vec<AnimTransformNodeData> {
+    match node_order {
+        NodeOrder::Lowercase => reference_nodes
+            .into_iter()
+            .chain(added_nodes)
+            .sorted_by_key(|x| x.name.to_lowercase())
+            .collect(),
+        NodeOrder::Ordinal => reference_nodes
+            .into_iter()
+            .chain(added_nodes)
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect(),
+        NodeOrder::Reference => reference_nodes.into_iter().chain(added_nodes).collect(),
+        NodeOrder::ReferenceInsert => {
+            let mut nodes = reference_nodes;
+            for added_node in added_nodes {
+                let modified_position = modified_node_names
+                    .iter()
+                    .position(|name| *name == added_node.raw_name)
+                    .unwrap_or(modified_node_names.len());
+                // Find the closest node before this one in the modified anim that has already been placed.
+                let insert_index = modified_node_names[..modified_position]
+                    .iter()
+                    .rev()
+                    .find_map(|previous_name| {
+                        nodes
+                            .iter()
+                            .position(|node| node.raw_name == *previous_name)
+                    })
+                    .map_or(0, |previous_index| previous_index + 1);
+                nodes.insert(insert_index, added_node);
+            }
+            nodes
+        }
+        NodeOrder::Modified => reference_nodes
+            .into_iter()
+            .chain(added_nodes)
+            .sorted_by_key(|node| {
+                modified_node_names
+                    .iter()
+                    .position(|name| *name == node.raw_name)
+                    .unwrap_or(modified_node_names.len())
+            })
+            .collect(),
+    }
+}
+
+/// Returns the first pair of vanilla bones whose relative order in `spliced_nodes` is different from the reference anim.
+pub fn find_reference_order_mismatch<'a>(
+    reference_nodes: &'a [AnimTransformNodeData],
+    spliced_nodes: &'a [AnimTransformNodeData],
+) -> Option<(&'a str, &'a str)> {
+    let spliced_reference_nodes = spliced_nodes.iter().filter(|spliced| {
+        reference_nodes
+            .iter()
+            .any(|r| r.raw_name == spliced.raw_name)
+    });
+
+    reference_nodes
+        .iter()
+        .zip(spliced_reference_nodes)
+        .find(|(reference, spliced)| reference.raw_name != spliced.raw_name)
+        .map(|(reference, spliced)| (spliced.name.as_str(), reference.name.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes(names: &[&str]) -> Vec<AnimTransformNodeData> {
+        names
+            .iter()
+            .map(|name| AnimTransformNodeData {
+                raw_name: SsbhString::from(*name),
+                name: name.to_string(),
+                tracks: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn names(nodes: &[AnimTransformNodeData]) -> Vec<&str> {
+        nodes.iter().map(|node| node.name.as_str()).collect()
+    }
+
+    fn order(
+        node_order: NodeOrder,
+        reference: &[&str],
+        added: &[&str],
+        modified: &[&str],
+    ) -> Vec<String> {
+        let modified_node_names = modified
+            .iter()
+            .map(|name| SsbhString::from(*name))
+            .collect::<Vec<_>>();
+        names(&order_transform_nodes(
+            node_order,
+            nodes(reference),
+            nodes(added),
+            &modified_node_names,
+        ))
+        .into_iter()
+        .map(String::from)
+        .collect()
+    }
+
+    #[test]
+    fn sorted_orders() {
+        assert_eq!(
+            order(NodeOrder::Lowercase, &["b", "C"], &["a_1", "A"], &[]),
+            vec!["A", "a_1", "b", "C"]
+        );
+        assert_eq!(
+            order(NodeOrder::Ordinal, &["b", "C"], &["a_1", "A"], &[]),
+            vec!["A", "C", "a_1", "b"]
+        );
+    }
+
+    #[test]
+    fn reference_order_appends_added_bones() {
+        assert_eq!(
+            order(
+                NodeOrder::Reference,
+                &["Waist", "Hip"],
+                &["Helper"],
+                &["Helper", "Hip", "Waist"]
+            ),
+            vec!["Waist", "Hip", "Helper"]
+        );
+    }
+
+    #[test]
+    fn reference_insert_order_follows_the_modified_anim() {
+        assert_eq!(
+            order(
+                NodeOrder::ReferenceInsert,
+                &["Waist", "Hip"],
+                &["First", "Helper"],
+                &["First", "Hip", "Helper", "Waist"]
+            ),
+            vec!["First", "Waist", "Hip", "Helper"]
+        );
+    }
+
+    #[test]
+    fn modified_order_appends_missing_reference_bones() {
+        assert_eq!(
+            order(
+                NodeOrder::Modified,
+                &["Waist", "Hip", "Trans"],
+                &["Helper"],
+                &["Hip", "Helper", "Waist"]
+            ),
+            vec!["Hip", "Helper", "Waist", "Trans"]
+        );
+    }
+
+    #[test]
+    fn reference_order_mismatch_names_the_moved_bones() {
+        let reference = nodes(&["Waist", "Hip"]);
+
+        let spliced = nodes(&["Helper", "Hip", "Waist"]);
+        assert_eq!(
+            find_reference_order_mismatch(&reference, &spliced),
+            Some(("Hip", "Waist"))
+        );
+
+        let spliced = nodes(&["Waist", "Helper", "Hip"]);
+        assert_eq!(find_reference_order_mismatch(&reference, &spliced), None);
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use wifisafe_common::archive::{is_same_path, is_zip_path, write_zip};
+
+fn with_appended_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// Refuses an output path that's also one of the inputs, unless `in_place` is set.
+///
+/// `inputs` are a label for the message, like `modified folder`, and the path.
+pub fn check_output_path(output: &Path, inputs: &[(&str, &Path)], in_place: bool) -> Result<()> {
+    for (label, input) in inputs {
+        if !is_same_path(output, input) {
+            continue;
+        }
+        if in_place {
+            println!(
+                "WARNING: the output `{}` is the {label}, so it will be overwritten in place. The original files are backed up as `.bak` first.",
+                output.display()
+            );
+        } else {
+            return Err(anyhow::format_err!(
+                "The output `{}` is the same as the {label} `{}`, so it would be overwritten! Use --in_place to do it anyway, with a backup of every overwritten file.",
+                output.display(),
+                input.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Writes an output file with `write`, without leaving a half written file behind if it fails.
+///
+/// The data is written to a temporary file next to `path`, which then replaces `path`.
+/// When `backup` is set and `path` already exists, it's copied to `<path>.bak` first. An existing backup is kept,
+/// since it's the oldest and most likely to be the original file.
+pub fn write_output(
+    path: &Path,
+    backup: bool,
+    write: impl FnOnce(&Path) -> Result<()>,
+) -> Result<()> {
+    if backup && path.exists() {
+        let backup_path = with_appended_extension(path, "bak");
+        if !backup_path.exists() {
+            fs::copy(path, &backup_path).with_context(|| {
+                format!(
+                    "could not back up `{}` to `{}`",
+                    path.display(),
+                    backup_path.display()
+                )
+            })?;
+        }
+    }
+
+    let temp_path = with_appended_extension(path, "tmp");
+    if let Err(e) = write(&temp_path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    fs::rename(&temp_path, path).with_context(|| {
+        format!(
+            "could not move `{}` to `{}`",
+            temp_path.display(),
+            path.display()
+        )
+    })
+}
+
+/// Where batch mode writes its anims, which is either a folder or a zip file.
+pub enum BatchOutput {
+    Folder(PathBuf),
+    /// The files are kept in memory until [BatchOutput::finish] writes the whole zip file at once.
+    Zip {
+        path: PathBuf,
+        files: Vec<(String, Vec<u8>)>,
+    },
+}
+
+impl BatchOutput {
+    /// A path ending in `.zip` is written as a zip file, anything else as a folder.
+    pub fn new(path: &Path) -> Result<Self> {
+        if is_zip_path(path) {
+            if let Some(parent) = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                fs::create_dir_all(parent).with_context(|| {
+                    format!("could not create the folder `{}`", parent.display())
+                })?;
+            }
+            Ok(Self::Zip {
+                path: path.to_path_buf(),
+                files: Vec::new(),
+            })
+        } else {
+            fs::create_dir_all(path).with_context(|| {
+                format!("could not create the output folder `{}`", path.display())
+            })?;
+            Ok(Self::Folder(path.to_path_buf()))
+        }
+    }
+
+    /// Where a file written to `entry` ends up, like `output/a00wait1.nuanmb` or `output.zip/c00/a00wait1.nuanmb`.
+    pub fn path(&self, entry: &Path) -> PathBuf {
+        match self {
+            Self::Folder(path) | Self::Zip { path, .. } => path.join(entry),
+        }
+    }
+
+    /// Writes a file to `entry`, which can be in subfolders, and returns its path like [BatchOutput::path].
+    pub fn write(&mut self, entry: &Path, bytes: Vec<u8>, backup: bool) -> Result<PathBuf> {
+        let path = self.path(entry);
+        match self {
+            Self::Folder(_) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).with_context(|| {
+                        format!("could not create the folder `{}`", parent.display())
+                    })?;
+                }
+                write_output(&path, backup, |temp_path| {
+                    fs::write(temp_path, &bytes)
+                        .with_context(|| format!("could not write `{}`", path.display()))
+                })?;
+            }
+            Self::Zip { files, .. } => {
+                let entry = entry
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.push((entry, bytes));
+            }
+        }
+        Ok(path)
+    }
+
+    /// Writes the zip file, if this is one. Nothing is left to do for a folder.
+    pub fn finish(self, backup: bool) -> Result<()> {
+        match self {
+            Self::Folder(_) => Ok(()),
+            Self::Zip { path, files } => {
+                write_output(&path, backup, |temp_path| write_zip(temp_path, &files))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wifisafe_common::test_anims::TestDir;
+
+    #[test]
+    fn output_folder_same_as_input_is_refused() {
+        let dir = TestDir::new();
+        let input = dir.path.join("modified");
+        fs::create_dir_all(&input).unwrap();
+
+        let same = dir.path.join("modified").join("..").join("modified");
+        assert!(check_output_path(&same, &[("modified folder", &input)], false).is_err());
+        assert!(check_output_path(&same, &[("modified folder", &input)], true).is_ok());
+        assert!(check_output_path(
+            &dir.path.join("output"),
+            &[("modified folder", &input)],
+            false
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn in_place_write_keeps_the_first_backup() {
+        let dir = TestDir::new();
+        let path = dir.path.join("a00wait1.nuanmb");
+        fs::write(&path, "original").unwrap();
+
+        write_output(&path, true, |temp| Ok(fs::write(temp, "first")?)).unwrap();
+        write_output(&path, true, |temp| Ok(fs::write(temp, "second")?)).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(
+            fs::read_to_string(dir.path.join("a00wait1.nuanmb.bak")).unwrap(),
+            "original"
+        );
+    }
+
+    #[test]
+    fn failed_write_leaves_the_output_untouched() {
+        let dir = TestDir::new();
+        let path = dir.path.join("a00wait1.nuanmb");
+        fs::write(&path, "original").unwrap();
+
+        let result = write_output(&path, false, |temp| {
+            fs::write(temp, "half")?;
+            Err(anyhow::format_err!("failed"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        assert!(!dir.path.join("a00wait1.nuanmb.tmp").exists());
+    }
+
+    #[test]
+    fn zip_output_keeps_entry_folders() {
+        let dir = TestDir::new();
+        let zip_path = dir.path.join("output").join("spliced.zip");
+
+        let mut batch_output = BatchOutput::new(&zip_path).unwrap();
+        let entry = Path::new("fighter").join("c00").join("a00wait1.nuanmb");
+        let output = batch_output.write(&entry, b"anim".to_vec(), false).unwrap();
+        assert!(!zip_path.exists());
+        batch_output.finish(false).unwrap();
+
+        assert_eq!(output, zip_path.join(&entry));
+        assert_eq!(wifisafe_common::archive::read(&output).unwrap(), b"anim");
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What went into a spliced anim.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SpliceStats {
+    pub reference_bones_kept: usize,
+    pub bones_added: usize,
+    /// Where the Visibility group came from, or `None` if the spliced anim doesn't have one.
+    pub visibility_source: Option<String>,
+    /// Where the Material group came from, or `None` if the spliced anim doesn't have one.
+    pub material_source: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SplicedEntry {
+    pub anim: String,
+    pub reference: PathBuf,
+    pub output: PathBuf,
+    #[serde(flatten)]
+    pub stats: SpliceStats,
+}
+
+#[derive(Serialize)]
+pub struct UnchangedEntry {
+    pub anim: String,
+    pub output: PathBuf,
+}
+
+/// A modified anim without a vanilla counterpart that was copied to the output folder as is.
+#[derive(Serialize)]
+pub struct CopiedEntry {
+    pub anim: String,
+    pub source: PathBuf,
+    pub output: PathBuf,
+}
+
+#[derive(Serialize)]
+pub struct SkippedEntry {
+    pub anim: String,
+    pub reason: String,
+}
+
+#[derive(Serialize)]
+pub struct FailedEntry {
+    pub anim: String,
+    pub error: String,
+}
+
+/// Every anim of a batch run and what happened to it.
+#[derive(Default, Serialize)]
+pub struct BatchReport {
+    pub spliced: Vec<SplicedEntry>,
+    /// Anims that weren't spliced again in incremental mode, since nothing changed.
+    pub unchanged: Vec<UnchangedEntry>,
+    pub copied: Vec<CopiedEntry>,
+    pub skipped: Vec<SkippedEntry>,
+    pub failed: Vec<FailedEntry>,
+}
+
+impl BatchReport {
+    pub fn print_summary(&self) {
+        println!(
+            "Total Modified Anims: {}",
+            self.spliced.len()
+                + self.unchanged.len()
+                + self.copied.len()
+                + self.skipped.len()
+                + self.failed.len()
+        );
+        println!("Spliced Count: {}", self.spliced.len());
+        println!("Unchanged Count: {}", self.unchanged.len());
+        println!("Copied Count: {}", self.copied.len());
+        println!("Skip Count: {}", self.skipped.len());
+        println!("Failed Count: {}", self.failed.len());
+        println!(
+            "Reference Bones Kept: {}",
+            self.spliced
+                .iter()
+                .map(|entry| entry.stats.reference_bones_kept)
+                .sum::<usize>()
+        );
+        println!(
+            "Bones Added: {}",
+            self.spliced
+                .iter()
+                .map(|entry| entry.stats.bones_added)
+                .sum::<usize>()
+        );
+    }
+
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).context("could not serialize the report")?;
+        fs::write(path, json)
+            .with_context(|| format!("could not write the report to `{}`", path.display()))
+    }
+}
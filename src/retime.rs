@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use ssbh_data::anim_data::{AnimData, GroupData, GroupType, TrackValues};
+use ssbh_lib::prelude::*;
+
+use crate::AnimTransformNodeData;
+
+/// Describes how the frames of an added bone map onto the frames of the reference anim.
+///
+/// For every output frame `f`, the source frame is `(f - offset) * scale`, rounded to the nearest frame
+/// of the trimmed source values. Out of range frames are either clamped or wrapped around if `looping` is set.
+#[derive(Clone, Debug, Serialize)]
+pub struct FrameMapping {
+    pub offset: f32,
+    pub scale: f32,
+    pub trim_start: Option<usize>,
+    pub trim_end: Option<usize>,
+    pub looping: bool,
+}
+
+/// Parses `--frame_offset`, which has to be a finite number of frames.
+pub fn parse_frame_offset(arg: &str) -> Result<f32, String> {
+    let offset: f32 = arg
+        .trim()
+        .parse()
+        .map_err(|_| format!("the frame offset `{arg}` isn't a number"))?;
+    if offset.is_finite() {
+        Ok(offset)
+    } else {
+        Err(format!("the frame offset `{arg}` must be a finite number"))
+    }
+}
+
+/// Parses `--frame_scale`, which has to be finite and greater than 0 for the remapped frames to mean anything.
+pub fn parse_frame_scale(arg: &str) -> Result<f32, String> {
+    let scale: f32 = arg
+        .trim()
+        .parse()
+        .map_err(|_| format!("the frame scale `{arg}` isn't a number"))?;
+    if scale.is_finite() && scale > 0.0 {
+        Ok(scale)
+    } else {
+        Err(format!(
+            "the frame scale `{arg}` must be a finite number greater than 0"
+        ))
+    }
+}
+
+impl FrameMapping {
+    fn remap<T: Clone>(&self, values: &[T], frame_count: usize) -> Vec<T> {
+        if values.len() <= 1 {
+            // Constant tracks don't have any frames to remap.
+            return values.to_vec();
+        }
+        let last_index = values.len() - 1;
+        let start = self.trim_start.unwrap_or(0).min(last_index);
+        let end = self.trim_end.unwrap_or(last_index).clamp(start, last_index);
+        let trimmed = &values[start..=end];
+        let trimmed_len = trimmed.len() as i64;
+
+        (0..frame_count)
+            .map(|frame| {
+                let source_frame = ((frame as f32 - self.offset) * self.scale).round() as i64;
+                let index = if self.looping {
+                    source_frame.rem_euclid(trimmed_len)
+                } else {
+                    source_frame.clamp(0, trimmed_len - 1)
+                };
+                trimmed[index as usize].clone()
+            })
+            .collect()
+    }
+
+    fn apply(&self, values: &mut TrackValues, frame_count: usize) {
+        match values {
+            TrackValues::Transform(v) => *v = self.remap(v, frame_count),
+            TrackValues::UvTransform(v) => *v = self.remap(v, frame_count),
+            TrackValues::Float(v) => *v = self.remap(v, frame_count),
+            TrackValues::PatternIndex(v) => *v = self.remap(v, frame_count),
+            TrackValues::Boolean(v) => *v = self.remap(v, frame_count),
+            TrackValues::Vector4(v) => *v = self.remap(v, frame_count),
+        }
+    }
+}
+
+/// Decodes the Transform nodes named in `node_names` from the modified anim, remaps their frames
+/// to fit the reference's `final_frame_index`, and re-encodes just those nodes.
+pub fn reencode_transform_nodes(
+    modified_anim: &Anim,
+    node_names: &[String],
+    mapping: &FrameMapping,
+    final_frame_index: f32,
+) -> Result<Vec<AnimTransformNodeData>> {
+    if node_names.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let modified_anim_data = AnimData::try_from(modified_anim)
+        .context("could not decode the modified anim with ssbh_data")?;
+
+    let frame_count = final_frame_index as usize + 1;
+    let nodes = modified_anim_data
+        .groups
+        .into_iter()
+        .filter(|group| group.group_type == GroupType::Transform)
+        .flat_map(|group| group.nodes)
+        .filter(|node| node_names.contains(&node.name))
+        .map(|mut node| {
+            for track in &mut node.tracks {
+                mapping.apply(&mut track.values, frame_count);
+            }
+            node
+        })
+        .collect();
+
+    let retimed_anim_data = AnimData {
+        major_version: modified_anim_data.major_version,
+        minor_version: modified_anim_data.minor_version,
+        final_frame_index,
+        groups: vec![GroupData {
+            group_type: GroupType::Transform,
+            nodes,
+        }],
+    };
+
+    let retimed_anim = Anim::try_from(&retimed_anim_data)
+        .context("could not re-encode the retimed added bones with ssbh_data")?;
+
+    match &retimed_anim {
+        Anim::V20 { groups, buffer, .. } | Anim::V21 { groups, buffer, .. } => Ok(groups
+            .elements
+            .iter()
+            .filter(|group| group.group_type == ssbh_lib::formats::anim::GroupType::Transform)
+            .flat_map(|group| group.nodes.elements.iter())
+            .map(|node| AnimTransformNodeData::from(node, buffer))
+            .collect()),
+        Anim::V12 { .. } => Err(anyhow::format_err!(
+            "ssbh_data re-encoded the added bones as a v12 anim, which is not supported!"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity() -> FrameMapping {
+        FrameMapping {
+            offset: 0.0,
+            scale: 1.0,
+            trim_start: None,
+            trim_end: None,
+            looping: false,
+        }
+    }
+
+    const VALUES: [u32; 5] = [0, 1, 2, 3, 4];
+
+    #[test]
+    fn default_mapping_keeps_the_frames() {
+        assert_eq!(vec![0, 1, 2, 3, 4], identity().remap(&VALUES, 5));
+        // Extra frames hold the last value.
+        assert_eq!(vec![0, 1, 2, 3, 4, 4, 4], identity().remap(&VALUES, 7));
+        assert_eq!(vec![0, 1, 2], identity().remap(&VALUES, 3));
+    }
+
+    #[test]
+    fn constant_tracks_are_not_remapped() {
+        let mapping = FrameMapping {
+            offset: 2.0,
+            ..identity()
+        };
+        assert_eq!(vec![7], mapping.remap(&[7], 5));
+    }
+
+    #[test]
+    fn offset_shifts_the_frames() {
+        let mapping = FrameMapping {
+            offset: 2.0,
+            ..identity()
+        };
+        assert_eq!(vec![0, 0, 0, 1, 2], mapping.remap(&VALUES, 5));
+
+        let mapping = FrameMapping {
+            offset: -2.0,
+            ..mapping
+        };
+        assert_eq!(vec![2, 3, 4, 4, 4], mapping.remap(&VALUES, 5));
+    }
+
+    #[test]
+    fn scale_changes_the_speed() {
+        let mapping = FrameMapping {
+            scale: 2.0,
+            ..identity()
+        };
+        assert_eq!(vec![0, 2, 4, 4, 4], mapping.remap(&VALUES, 5));
+
+        let mapping = FrameMapping {
+            scale: 0.5,
+            ..mapping
+        };
+        // Halfway frames round away from 0.
+        assert_eq!(vec![0, 1, 1, 2, 2], mapping.remap(&VALUES, 5));
+    }
+
+    #[test]
+    fn trim_drops_frames_before_and_after() {
+        let mapping = FrameMapping {
+            trim_start: Some(1),
+            trim_end: Some(3),
+            ..identity()
+        };
+        assert_eq!(vec![1, 2, 3, 3, 3], mapping.remap(&VALUES, 5));
+
+        // Out of range trims are clamped to the values.
+        let mapping = FrameMapping {
+            trim_start: Some(10),
+            trim_end: Some(2),
+            ..identity()
+        };
+        assert_eq!(vec![4, 4], mapping.remap(&VALUES, 2));
+    }
+
+    #[test]
+    fn looping_wraps_around() {
+        let mapping = FrameMapping {
+            looping: true,
+            ..identity()
+        };
+        assert_eq!(vec![0, 1, 2, 3, 4, 0, 1], mapping.remap(&VALUES, 7));
+
+        let mapping = FrameMapping {
+            offset: 1.0,
+            trim_start: Some(1),
+            trim_end: Some(3),
+            looping: true,
+            ..identity()
+        };
+        assert_eq!(vec![3, 1, 2, 3, 1], mapping.remap(&VALUES, 5));
+    }
+
+    #[test]
+    fn frame_scale_must_be_finite_and_positive() {
+        assert_eq!(Ok(0.5), parse_frame_scale("0.5"));
+        assert!(parse_frame_scale("0").is_err());
+        assert!(parse_frame_scale("-1").is_err());
+        assert!(parse_frame_scale("NaN").is_err());
+        assert!(parse_frame_scale("inf").is_err());
+        assert!(parse_frame_scale("fast").is_err());
+    }
+
+    #[test]
+    fn frame_offset_must_be_finite() {
+        assert_eq!(Ok(-3.0), parse_frame_offset("-3"));
+        assert!(parse_frame_offset("NaN").is_err());
+        assert!(parse_frame_offset("-inf").is_err());
+    }
+}
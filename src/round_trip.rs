@@ -0,0 +1,164 @@
+use anyhow::{Context, Result};
+use std::io::Cursor;
+use std::path::Path;
+use wifisafe_common::archive;
+
+use crate::{splice_anim, SpliceOptions};
+
+/// Splices the reference anim with itself and returns the offset of the first byte that's different from the reference file.
+///
+/// Nothing is added or replaced when an anim is spliced with itself, so any difference comes from how the groups
+/// and buffer are rebuilt, and would be in every anim spliced from this reference too.
+pub fn round_trip_difference(
+    reference_anim_path: &Path,
+    options: &SpliceOptions,
+) -> Result<Option<usize>> {
+    let reference_bytes = archive::read(reference_anim_path).with_context(|| {
+        format!(
+            "could not read reference anim `{}`",
+            reference_anim_path.display()
+        )
+    })?;
+
+    // Only the options that change the layout matter, the rest would just repeat their reports.
+    // The reference's own name conflicts are always kept, so the name conflict policy doesn't apply.
+    let round_trip_options = SpliceOptions {
+        node_order: options.node_order,
+        buffer_alignment: options.buffer_alignment,
+        deduplicate_tracks: options.deduplicate_tracks,
+        ..Default::default()
+    };
+    let reference_anim_path = reference_anim_path.to_path_buf();
+    let spliced_anim = splice_anim(
+        &reference_anim_path,
+        &[Some(reference_anim_path.clone())],
+        &round_trip_options,
+    )?;
+
+    let mut writer = Cursor::new(Vec::new());
+    spliced_anim
+        .anim
+        .write(&mut writer)
+        .context("could not write the reference anim spliced with itself")?;
+    Ok(first_difference(&reference_bytes, writer.get_ref()))
+}
+
+fn first_difference(expected: &[u8], actual: &[u8]) -> Option<usize> {
+    expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual)
+        .or_else(|| (expected.len() != actual.len()).then_some(expected.len().min(actual.len())))
+}
+
+/// Runs [round_trip_difference] and prints a warning if the reference anim isn't reproduced.
+pub fn report_round_trip(reference_anim_path: &Path, options: &SpliceOptions) {
+    match round_trip_difference(reference_anim_path, options) {
+        Ok(None) => {}
+        Ok(Some(offset)) => println!(
+            "WARNING: splicing the reference anim `{}` with itself doesn't reproduce it byte for byte, the first difference is at byte `{offset}`. Its spliced anims won't be laid out like vanilla.",
+            reference_anim_path.display()
+        ),
+        Err(e) => println!(
+            "WARNING: could not check that the reference anim `{}` round trips, error=`{e:?}`",
+            reference_anim_path.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssbh_data::anim_data::GroupType;
+    use wifisafe_common::test_anims::*;
+
+    fn assert_round_trips(anim_data: AnimData) {
+        let dir = TestDir::new();
+        let path = dir.write_anim("reference.nuanmb", &anim_data);
+        assert_eq!(
+            round_trip_difference(&path, &SpliceOptions::default()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn first_difference_of_identical_bytes() {
+        assert_eq!(first_difference(&[1, 2, 3], &[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn first_difference_of_different_bytes() {
+        assert_eq!(first_difference(&[1, 2, 3], &[1, 4, 3]), Some(1));
+    }
+
+    #[test]
+    fn first_difference_of_different_lengths() {
+        assert_eq!(first_difference(&[1, 2, 3], &[1, 2]), Some(2));
+        assert_eq!(first_difference(&[1, 2], &[1, 2, 3]), Some(2));
+    }
+
+    #[test]
+    fn round_trip_transform_only() {
+        for minor_version in [0, 1] {
+            assert_round_trips(anim_data(
+                minor_version,
+                vec![group(
+                    GroupType::Transform,
+                    vec![transform_node("ArmL", 1.0), transform_node("ArmR", 2.0)],
+                )],
+            ));
+        }
+    }
+
+    #[test]
+    fn round_trip_every_group() {
+        for minor_version in [0, 1] {
+            assert_round_trips(anim_data(
+                minor_version,
+                vec![
+                    group(
+                        GroupType::Transform,
+                        vec![transform_node("Hip", 0.0), transform_node("Waist", 1.0)],
+                    ),
+                    group(
+                        GroupType::Visibility,
+                        vec![
+                            visibility_node("EyeL", true),
+                            visibility_node("EyeR", false),
+                        ],
+                    ),
+                    group(
+                        GroupType::Material,
+                        vec![material_node("EyeL", "CustomVector6", 0.5)],
+                    ),
+                ],
+            ));
+        }
+    }
+
+    #[test]
+    fn round_trip_with_name_conflicts() {
+        // Splicing the reference with itself must not refuse the reference's own conflicts.
+        assert_round_trips(anim_data(
+            1,
+            vec![group(
+                GroupType::Visibility,
+                vec![
+                    visibility_node("EyeL", true),
+                    visibility_node("eyel", false),
+                ],
+            )],
+        ));
+    }
+
+    #[test]
+    fn round_trip_visibility_only() {
+        assert_round_trips(anim_data(
+            1,
+            vec![group(
+                GroupType::Visibility,
+                vec![visibility_node("Body", true)],
+            )],
+        ));
+    }
+}
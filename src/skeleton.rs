@@ -0,0 +1,177 @@
+use anyhow::Result;
+use ssbh_data::prelude::*;
+use std::path::Path;
+use wifisafe_common::validation::get_ancestors;
+
+/// A `.nusktb`, of which the splicer only needs the bone names and parents of the hierarchy.
+#[derive(Debug)]
+pub struct Skeleton {
+    skel: SkelData,
+}
+
+impl Skeleton {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let skel = SkelData::from_file(path).map_err(|e| {
+            anyhow::format_err!("could not read skeleton `{}`, error=`{e}`", path.display())
+        })?;
+        Ok(Self { skel })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.skel.bones.iter().any(|bone| bone.name == name)
+    }
+
+    /// The ancestors of a bone, starting from its parent and ending at the root.
+    pub fn ancestors(&self, name: &str) -> Vec<&str> {
+        get_ancestors(&self.skel, name)
+    }
+
+    /// The path from the root to the bone, like `Trans > Rot > Hip > Waist`.
+    pub fn hierarchy_path(&self, name: &str) -> String {
+        if !self.contains(name) {
+            return format!("{name} (not in skeleton)");
+        }
+        let mut path = self.ancestors(name);
+        path.reverse();
+        path.push(name);
+        path.join(" > ")
+    }
+}
+
+/// Finds the vanilla bones with an ancestor whose data came from a modified anim, along with that ancestor.
+///
+/// Only the `.nusktb` parents are followed. Helper bone constraints from a `.nuhlpb` can also move a vanilla bone
+/// based on an added bone, but they aren't read, so those splices aren't found.
+pub fn find_risky_splices<'a>(
+    skeleton: &'a Skeleton,
+    reference_bone_names: &'a [String],
+    added_bone_names: &[&String],
+) -> Vec<(&'a str, &'a str)> {
+    reference_bone_names
+        .iter()
+        .filter_map(|name| {
+            let changed_ancestor = skeleton
+                .ancestors(name)
+                .into_iter()
+                .find(|ancestor| added_bone_names.iter().any(|added| added == ancestor))?;
+            Some((name.as_str(), changed_ancestor))
+        })
+        .collect()
+}
+
+/// Prints where each added Transform bone sits in the skeleton, and warns about decisions that the skeleton shows
+/// to be questionable. The kept vanilla bones are only mentioned when one of them is at risk.
+pub fn report_splice_hierarchy(
+    skeleton: &Skeleton,
+    reference_bone_names: &[String],
+    added_bone_names_by_source: &[Vec<String>],
+) {
+    let added_bones = added_bone_names_by_source
+        .iter()
+        .enumerate()
+        .flat_map(|(source_index, names)| names.iter().map(move |name| (name, source_index)))
+        .collect::<Vec<_>>();
+
+    for (name, source_index) in &added_bones {
+        if skeleton.contains(name) {
+            println!(
+                "ADDED: `{name}` from modified anim #{}, hierarchy=`{}`",
+                source_index + 1,
+                skeleton.hierarchy_path(name)
+            );
+        } else {
+            println!(
+                "WARNING: the added bone `{name}` from modified anim #{} is not in the skeleton, so it won't move anything in game.",
+                source_index + 1
+            );
+        }
+    }
+
+    let added_bone_names = added_bones
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+    for (name, ancestor) in find_risky_splices(skeleton, reference_bone_names, &added_bone_names) {
+        println!(
+            "WARNING: the locked bone `{name}` is a descendant of `{ancestor}`, whose data came from a modified anim, so its world transform will change! hierarchy=`{}`",
+            skeleton.hierarchy_path(name)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssbh_data::skel_data::BoneData;
+    use ssbh_lib::formats::skel::BillboardType;
+
+    fn skeleton_of(bones: &[(&str, Option<usize>)]) -> Skeleton {
+        Skeleton {
+            skel: SkelData {
+                major_version: 1,
+                minor_version: 0,
+                bones: bones
+                    .iter()
+                    .map(|(name, parent_index)| BoneData {
+                        name: name.to_string(),
+                        transform: [[0.0; 4]; 4],
+                        parent_index: *parent_index,
+                        billboard_type: BillboardType::Disabled,
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    /// Trans > Rot > Hip > (Waist > Bust, HelperBone)
+    fn skeleton() -> Skeleton {
+        skeleton_of(&[
+            ("Trans", None),
+            ("Rot", Some(0)),
+            ("Hip", Some(1)),
+            ("Waist", Some(2)),
+            ("Bust", Some(3)),
+            ("HelperBone", Some(2)),
+        ])
+    }
+
+    #[test]
+    fn ancestors_end_at_the_root() {
+        let skeleton = skeleton();
+        assert_eq!(
+            skeleton.ancestors("Bust"),
+            vec!["Waist", "Hip", "Rot", "Trans"]
+        );
+        assert!(skeleton.ancestors("Trans").is_empty());
+        assert!(skeleton.ancestors("Missing").is_empty());
+        assert_eq!(
+            skeleton.hierarchy_path("HelperBone"),
+            "Trans > Rot > Hip > HelperBone"
+        );
+        assert_eq!(
+            skeleton.hierarchy_path("Missing"),
+            "Missing (not in skeleton)"
+        );
+    }
+
+    #[test]
+    fn parent_cycles_stop() {
+        let skeleton = skeleton_of(&[("A", Some(1)), ("B", Some(0))]);
+        assert_eq!(skeleton.ancestors("A").len(), 2);
+    }
+
+    #[test]
+    fn vanilla_bones_below_added_bones_are_risky() {
+        let skeleton = skeleton();
+        let reference_bone_names = vec!["Hip".to_string(), "Bust".to_string()];
+
+        let waist = "Waist".to_string();
+        assert_eq!(
+            find_risky_splices(&skeleton, &reference_bone_names, &[&waist]),
+            vec![("Bust", "Waist")]
+        );
+
+        let helper_bone = "HelperBone".to_string();
+        assert!(find_risky_splices(&skeleton, &reference_bone_names, &[&helper_bone]).is_empty());
+    }
+}
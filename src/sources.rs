@@ -0,0 +1,100 @@
+use ssbh_lib::formats::anim::GroupType;
+use std::fmt;
+use std::str::FromStr;
+
+/// Picks which modified anim a whole group, or a single node of a group, is taken from.
+///
+/// Written as `<group>=<number>` or `<group>/<node>=<number>`, where `<number>` is the 1-based position of the
+/// modified anim (or modified folder in batch mode) on the command line. For example `visibility=2` or `transform/HelperBone=1`.
+#[derive(Clone, Debug)]
+pub struct SourceRule {
+    pub group_type: GroupType,
+    pub node_name: Option<String>,
+    pub source_index: usize,
+}
+
+fn parse_group_type(group: &str) -> Option<GroupType> {
+    match group.to_lowercase().as_str() {
+        "transform" => Some(GroupType::Transform),
+        "visibility" => Some(GroupType::Visibility),
+        "material" => Some(GroupType::Material),
+        _ => None,
+    }
+}
+
+impl FromStr for SourceRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let (target, source_number) = rule
+            .rsplit_once('=')
+            .ok_or_else(|| format!("the source rule `{rule}` is missing an `=<number>`"))?;
+
+        let source_number: usize = source_number
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| {
+                format!("`{source_number}` is not a modified anim number, they start at 1")
+            })?;
+
+        let (group, node_name) = match target.split_once('/') {
+            Some((group, node_name)) => (group, Some(node_name.to_string())),
+            None => (target, None),
+        };
+
+        let group_type = parse_group_type(group.trim()).ok_or_else(|| {
+            format!("`{group}` is not a group, expected `transform`, `visibility` or `material`")
+        })?;
+
+        Ok(Self {
+            group_type,
+            node_name,
+            source_index: source_number - 1,
+        })
+    }
+}
+
+impl fmt::Display for SourceRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.group_type)?;
+        if let Some(node_name) = &self.node_name {
+            write!(f, "/{node_name}")?;
+        }
+        write!(f, "={}", self.source_index + 1)
+    }
+}
+
+/// Returns the indices of the modified anims in the order they should be searched for a group or node,
+/// most preferred first. A node rule beats a group rule, and a group rule beats the command line order.
+/// Later rules override earlier ones for the same group or node.
+pub fn source_order(
+    rules: &[SourceRule],
+    group_type: GroupType,
+    node_name: Option<&str>,
+    source_count: usize,
+) -> Vec<usize> {
+    let node_rule = node_name.and_then(|node_name| {
+        rules.iter().rev().find(|rule| {
+            rule.group_type == group_type && rule.node_name.as_deref() == Some(node_name)
+        })
+    });
+    let group_rule = rules
+        .iter()
+        .rev()
+        .find(|rule| rule.group_type == group_type && rule.node_name.is_none());
+
+    let mut order: Vec<usize> = Vec::with_capacity(source_count);
+    for source_index in node_rule
+        .into_iter()
+        .chain(group_rule)
+        .map(|rule| rule.source_index)
+        .chain(0..source_count)
+    {
+        if !order.contains(&source_index) {
+            order.push(source_index);
+        }
+    }
+    order
+}
@@ -0,0 +1,620 @@
+use crate::name_conflicts::NameConflictPolicy;
+use crate::sources::SourceRule;
+use crate::{do_batch_mode, splice_anim, Args, BatchOptions, SpliceOptions};
+use anyhow::Result;
+use clap::Parser;
+use ssbh_data::anim_data::{GroupType, TrackValues};
+use ssbh_lib::prelude::*;
+use wifisafe_common::test_anims::*;
+
+/// Writes the anims to a temporary folder and splices them.
+fn splice(
+    reference: &AnimData,
+    modified: &[AnimData],
+    options: &SpliceOptions,
+) -> Result<(Anim, AnimData)> {
+    let dir = TestDir::new();
+    let reference_path = dir.write_anim("reference.nuanmb", reference);
+    let modified_paths = modified
+        .iter()
+        .enumerate()
+        .map(|(i, anim_data)| Some(dir.write_anim(&format!("modified{i}.nuanmb"), anim_data)))
+        .collect::<Vec<_>>();
+    let anim = splice_anim(&reference_path, &modified_paths, options)?.anim;
+    let anim_data = AnimData::try_from(&anim)?;
+    Ok((anim, anim_data))
+}
+
+fn node_names(anim_data: &AnimData, group_type: GroupType) -> Vec<&str> {
+    anim_data
+        .groups
+        .iter()
+        .filter(|group| group.group_type == group_type)
+        .flat_map(|group| group.nodes.iter())
+        .map(|node| node.name.as_str())
+        .collect()
+}
+
+fn track_values<'a>(
+    anim_data: &'a AnimData,
+    group_type: GroupType,
+    node_name: &str,
+) -> Option<&'a TrackValues> {
+    anim_data
+        .groups
+        .iter()
+        .find(|group| group.group_type == group_type)?
+        .nodes
+        .iter()
+        .find(|node| node.name == node_name)?
+        .tracks
+        .first()
+        .map(|track| &track.values)
+}
+
+#[test]
+fn added_bones_are_spliced_and_vanilla_bones_are_kept() {
+    for minor_version in [0, 1] {
+        let reference = anim_data(
+            minor_version,
+            vec![group(
+                GroupType::Transform,
+                vec![transform_node("Hip", 0.0), transform_node("Waist", 1.0)],
+            )],
+        );
+        let modified = anim_data(
+            minor_version,
+            vec![group(
+                GroupType::Transform,
+                vec![
+                    transform_node("Hip", 10.0),
+                    transform_node("HelperBone", 20.0),
+                    transform_node("Waist", 30.0),
+                ],
+            )],
+        );
+
+        let (_, spliced) = splice(&reference, &[modified], &SpliceOptions::default()).unwrap();
+
+        assert_eq!(
+            node_names(&spliced, GroupType::Transform),
+            vec!["HelperBone", "Hip", "Waist"]
+        );
+        assert_eq!(
+            track_values(&spliced, GroupType::Transform, "Hip"),
+            Some(&translations(0.0))
+        );
+        assert_eq!(
+            track_values(&spliced, GroupType::Transform, "Waist"),
+            Some(&translations(1.0))
+        );
+        assert_eq!(
+            track_values(&spliced, GroupType::Transform, "HelperBone"),
+            Some(&translations(20.0))
+        );
+    }
+}
+
+#[test]
+fn version_of_reference_is_kept() {
+    let reference = anim_data(
+        0,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0)],
+        )],
+    );
+    let (anim, _) = splice(
+        &reference,
+        std::slice::from_ref(&reference),
+        &SpliceOptions::default(),
+    )
+    .unwrap();
+    assert!(matches!(anim, Anim::V20 { .. }));
+
+    let reference = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0)],
+        )],
+    );
+    let (anim, _) = splice(
+        &reference,
+        std::slice::from_ref(&reference),
+        &SpliceOptions::default(),
+    )
+    .unwrap();
+    assert!(matches!(anim, Anim::V21 { .. }));
+}
+
+#[test]
+fn visibility_and_material_come_from_modified() {
+    let reference = anim_data(
+        1,
+        vec![
+            group(GroupType::Transform, vec![transform_node("Hip", 0.0)]),
+            group(GroupType::Visibility, vec![visibility_node("EyeL", true)]),
+            group(
+                GroupType::Material,
+                vec![material_node("EyeL", "CustomVector6", 0.0)],
+            ),
+        ],
+    );
+    let modified = anim_data(
+        1,
+        vec![
+            group(GroupType::Transform, vec![transform_node("Hip", 5.0)]),
+            group(
+                GroupType::Visibility,
+                vec![
+                    visibility_node("EyeL", false),
+                    visibility_node("EyeR", true),
+                ],
+            ),
+            group(
+                GroupType::Material,
+                vec![material_node("EyeL", "CustomVector6", 1.0)],
+            ),
+        ],
+    );
+
+    let (_, spliced) = splice(
+        &reference,
+        std::slice::from_ref(&modified),
+        &SpliceOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        track_values(&spliced, GroupType::Transform, "Hip"),
+        Some(&translations(0.0))
+    );
+    for group_type in [GroupType::Visibility, GroupType::Material] {
+        let expected = modified
+            .groups
+            .iter()
+            .find(|group| group.group_type == group_type);
+        let actual = spliced
+            .groups
+            .iter()
+            .find(|group| group.group_type == group_type);
+        assert_eq!(actual, expected);
+    }
+}
+
+#[test]
+fn visibility_falls_back_to_reference() {
+    let reference = anim_data(
+        1,
+        vec![
+            group(GroupType::Transform, vec![transform_node("Hip", 0.0)]),
+            group(GroupType::Visibility, vec![visibility_node("EyeL", true)]),
+        ],
+    );
+    let modified = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0)],
+        )],
+    );
+
+    let (_, spliced) = splice(&reference, &[modified], &SpliceOptions::default()).unwrap();
+
+    assert_eq!(node_names(&spliced, GroupType::Visibility), vec!["EyeL"]);
+}
+
+#[test]
+fn empty_modified_transform_group_keeps_reference() {
+    let reference = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0), transform_node("Waist", 1.0)],
+        )],
+    );
+    let modified = anim_data(1, vec![group(GroupType::Transform, Vec::new())]);
+
+    let (_, spliced) = splice(&reference, &[modified], &SpliceOptions::default()).unwrap();
+
+    assert_eq!(
+        node_names(&spliced, GroupType::Transform),
+        vec!["Hip", "Waist"]
+    );
+}
+
+#[test]
+fn empty_anims_splice_to_an_empty_anim() {
+    let reference = anim_data(1, Vec::new());
+    let modified = anim_data(1, Vec::new());
+
+    let (_, spliced) = splice(&reference, &[modified], &SpliceOptions::default()).unwrap();
+
+    assert!(spliced.groups.is_empty());
+}
+
+#[test]
+fn transform_node_without_tracks_is_kept() {
+    let reference = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![node("Hip", Vec::new()), transform_node("Waist", 1.0)],
+        )],
+    );
+    let modified = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![node("Hip", Vec::new()), transform_node("Waist", 1.0)],
+        )],
+    );
+
+    let (_, spliced) = splice(&reference, &[modified], &SpliceOptions::default()).unwrap();
+
+    assert_eq!(
+        node_names(&spliced, GroupType::Transform),
+        vec!["Hip", "Waist"]
+    );
+    assert_eq!(track_values(&spliced, GroupType::Transform, "Hip"), None);
+}
+
+#[test]
+fn added_bones_come_from_first_modified_anim() {
+    let reference = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0)],
+        )],
+    );
+    let first = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0), transform_node("Helper", 1.0)],
+        )],
+    );
+    let second = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0), transform_node("Helper", 2.0)],
+        )],
+    );
+
+    let (_, spliced) = splice(
+        &reference,
+        &[first.clone(), second.clone()],
+        &SpliceOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        track_values(&spliced, GroupType::Transform, "Helper"),
+        Some(&translations(1.0))
+    );
+
+    let options = SpliceOptions {
+        source_rules: vec!["transform/Helper=2".parse::<SourceRule>().unwrap()],
+        ..Default::default()
+    };
+    let (_, spliced) = splice(&reference, &[first, second], &options).unwrap();
+    assert_eq!(
+        track_values(&spliced, GroupType::Transform, "Helper"),
+        Some(&translations(2.0))
+    );
+}
+
+#[test]
+fn source_rule_past_the_modified_anims_is_an_error() {
+    let reference = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0)],
+        )],
+    );
+    let options = SpliceOptions {
+        source_rules: vec!["visibility=2".parse::<SourceRule>().unwrap()],
+        ..Default::default()
+    };
+
+    assert!(splice(&reference, std::slice::from_ref(&reference), &options).is_err());
+}
+
+#[test]
+fn locked_bones_are_not_added() {
+    let reference = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0)],
+        )],
+    );
+    let modified = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![
+                transform_node("ArmR", 5.0),
+                transform_node("HelperBone", 20.0),
+                transform_node("Hip", 10.0),
+            ],
+        )],
+    );
+    let options = SpliceOptions {
+        // Scripts name bones in lowercase.
+        locked_bones: vec!["armr".to_string()],
+        ..Default::default()
+    };
+
+    let (_, spliced) = splice(&reference, &[modified], &options).unwrap();
+
+    assert_eq!(
+        node_names(&spliced, GroupType::Transform),
+        vec!["HelperBone", "Hip"]
+    );
+}
+
+#[test]
+fn json_modified_anim_adds_bones_and_replaces_named_nodes() {
+    let reference = anim_data(
+        1,
+        vec![
+            group(GroupType::Transform, vec![transform_node("Hip", 0.0)]),
+            group(
+                GroupType::Visibility,
+                vec![visibility_node("EyeL", true), visibility_node("EyeR", true)],
+            ),
+        ],
+    );
+    let json = anim_data(
+        1,
+        vec![
+            group(
+                GroupType::Transform,
+                vec![
+                    transform_node("Hip", 5.0),
+                    transform_node("HelperBone", 9.0),
+                ],
+            ),
+            group(GroupType::Visibility, vec![visibility_node("EyeL", false)]),
+        ],
+    );
+
+    let dir = TestDir::new();
+    let reference_path = dir.write_anim("reference.nuanmb", &reference);
+    let json_path = dir.write_json("helper_bones.json", &json);
+    let anim = splice_anim(
+        &reference_path,
+        &[Some(json_path)],
+        &SpliceOptions::default(),
+    )
+    .unwrap()
+    .anim;
+    let spliced = AnimData::try_from(&anim).unwrap();
+
+    assert_eq!(
+        node_names(&spliced, GroupType::Transform),
+        vec!["HelperBone", "Hip"]
+    );
+    assert_eq!(
+        track_values(&spliced, GroupType::Transform, "Hip"),
+        Some(&translations(0.0))
+    );
+    assert_eq!(
+        track_values(&spliced, GroupType::Transform, "HelperBone"),
+        Some(&translations(9.0))
+    );
+    assert_eq!(
+        track_values(&spliced, GroupType::Visibility, "EyeL"),
+        Some(&TrackValues::Boolean(vec![false; FRAME_COUNT]))
+    );
+    assert_eq!(
+        track_values(&spliced, GroupType::Visibility, "EyeR"),
+        Some(&TrackValues::Boolean(vec![true; FRAME_COUNT]))
+    );
+}
+
+#[test]
+fn json_modified_anim_keeps_reference_final_frame_index() {
+    let reference = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0)],
+        )],
+    );
+    let mut json = reference.clone();
+    json.final_frame_index = 10.0;
+
+    let dir = TestDir::new();
+    let reference_path = dir.write_anim("reference.nuanmb", &reference);
+    let json_path = dir.write_json("reference.json", &json);
+    let anim = splice_anim(
+        &reference_path,
+        &[Some(json_path)],
+        &SpliceOptions::default(),
+    )
+    .unwrap()
+    .anim;
+
+    assert_eq!(
+        AnimData::try_from(&anim).unwrap().final_frame_index,
+        reference.final_frame_index
+    );
+}
+
+#[test]
+fn batch_mode_matches_json_by_name() {
+    let reference = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0)],
+        )],
+    );
+
+    let dir = TestDir::new();
+    let reference_dir = dir.path.join("reference");
+    let modified_dir = dir.path.join("modified");
+    std::fs::create_dir_all(&reference_dir).unwrap();
+    std::fs::create_dir_all(&modified_dir).unwrap();
+    dir.write_anim("reference/a00wait1.nuanmb", &reference);
+    dir.write_json("modified/a00wait1.json", &reference);
+
+    let report = do_batch_mode(
+        &reference_dir,
+        &[modified_dir],
+        &dir.path.join("output"),
+        &BatchOptions::default(),
+        &SpliceOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(report.spliced.len(), 1);
+    assert!(dir.path.join("output").join("a00wait1.nuanmb").exists());
+}
+
+#[test]
+fn batch_mode_refuses_json_and_nuanmb_with_the_same_name() {
+    let reference = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0)],
+        )],
+    );
+
+    let dir = TestDir::new();
+    let reference_dir = dir.path.join("reference");
+    let modified_dir = dir.path.join("modified");
+    std::fs::create_dir_all(&reference_dir).unwrap();
+    std::fs::create_dir_all(&modified_dir).unwrap();
+    dir.write_anim("reference/a00wait1.nuanmb", &reference);
+    dir.write_anim("modified/a00wait1.nuanmb", &reference);
+    dir.write_json("modified/a00wait1.json", &reference);
+
+    let report = do_batch_mode(
+        &reference_dir,
+        &[modified_dir],
+        &dir.path.join("output"),
+        &BatchOptions::default(),
+        &SpliceOptions::default(),
+    )
+    .unwrap();
+
+    assert!(report.spliced.is_empty());
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].anim, "a00wait1");
+    assert!(!dir.path.join("output").join("a00wait1.nuanmb").exists());
+}
+
+#[test]
+fn reference_name_conflicts_are_kept() {
+    let reference = anim_data(
+        1,
+        vec![
+            group(GroupType::Transform, vec![transform_node("Hip", 0.0)]),
+            group(
+                GroupType::Visibility,
+                vec![
+                    visibility_node("EyeL", true),
+                    visibility_node("eyel", false),
+                ],
+            ),
+        ],
+    );
+    let modified = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0)],
+        )],
+    );
+
+    let (_, spliced) = splice(&reference, &[modified], &SpliceOptions::default()).unwrap();
+
+    assert_eq!(
+        node_names(&spliced, GroupType::Visibility),
+        vec!["EyeL", "eyel"]
+    );
+}
+
+#[test]
+fn modified_name_conflicts_are_refused_by_default() {
+    let reference = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0)],
+        )],
+    );
+    let modified = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("Hip", 0.0), transform_node("hip", 1.0)],
+        )],
+    );
+
+    assert!(splice(&reference, &[modified], &SpliceOptions::default()).is_err());
+}
+
+#[test]
+fn cross_anim_name_conflicts_are_refused_by_default() {
+    let reference = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("ArmR", 0.0)],
+        )],
+    );
+    let modified = anim_data(
+        1,
+        vec![group(
+            GroupType::Transform,
+            vec![transform_node("armr", 1.0)],
+        )],
+    );
+
+    assert!(splice(
+        &reference,
+        std::slice::from_ref(&modified),
+        &SpliceOptions::default()
+    )
+    .is_err());
+
+    let options = SpliceOptions {
+        name_conflicts: NameConflictPolicy::KeepAll,
+        ..Default::default()
+    };
+    let (_, spliced) = splice(&reference, &[modified], &options).unwrap();
+    assert_eq!(
+        node_names(&spliced, GroupType::Transform),
+        vec!["ArmR", "armr"]
+    );
+}
+
+#[test]
+fn retime_flags_require_retime_added_bones() {
+    let parse = |flags: &[&str]| {
+        Args::try_parse_from(
+            ["wifisafe_anim_splicer"]
+                .iter()
+                .chain(flags)
+                .collect::<Vec<_>>(),
+        )
+    };
+    for flag in [
+        &["--frame_offset", "2"][..],
+        &["--frame_scale", "2"],
+        &["--trim_start", "1"],
+        &["--trim_end", "1"],
+        &["--loop_added_bones"],
+    ] {
+        assert!(parse(flag).is_err());
+        assert!(parse(&[&["--retime_added_bones"][..], flag].concat()).is_ok());
+    }
+}
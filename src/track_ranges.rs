@@ -0,0 +1,135 @@
+use anyhow::Result;
+use ssbh_lib::formats::anim::{GroupType, TrackTypeV2};
+use ssbh_lib::prelude::*;
+use wifisafe_common::anim::track_data;
+
+/// Checks that every track of the anim points at data that's actually inside its buffer,
+/// so a corrupt anim is reported instead of panicking halfway through splicing.
+pub fn check_track_ranges(anim: &Anim, anim_label: &str) -> Result<()> {
+    let (groups, buffer) = match anim {
+        Anim::V20 { groups, buffer, .. } | Anim::V21 { groups, buffer, .. } => (groups, buffer),
+        Anim::V12 { .. } => return Ok(()),
+    };
+
+    for group in &groups.elements {
+        for node in &group.nodes.elements {
+            for track in &node.tracks.elements {
+                if track_data(track, buffer).is_none() {
+                    return Err(anyhow::format_err!(
+                        "The {anim_label} has a track whose data is outside of its buffer! Group={:?}, Node=`{}`, Track=`{}`, data_offset=`{}`, data_size=`{}`, buffer size=`{}`",
+                        group.group_type,
+                        node.name.to_string_lossy(),
+                        track.name.to_string_lossy(),
+                        track.data_offset,
+                        track.data_size,
+                        buffer.elements.len()
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Warns about Transform nodes that don't have exactly one Transform track.
+///
+/// Some vanilla anims like `fighter/kirby/motion/jackbody/c00/jackd00specialairnrandomend.nuanmb`
+/// are poorly formatted like this. Every track of these nodes is still spliced as is.
+pub fn report_transform_node_layouts(anim: &Anim, anim_label: &str) {
+    let groups = match anim {
+        Anim::V20 { groups, .. } | Anim::V21 { groups, .. } => groups,
+        Anim::V12 { .. } => return,
+    };
+
+    let transform_nodes = groups
+        .elements
+        .iter()
+        .filter(|group| group.group_type == GroupType::Transform)
+        .flat_map(|group| group.nodes.elements.iter());
+    for node in transform_nodes {
+        let name = node.name.to_string_lossy();
+        let tracks = &node.tracks.elements;
+        if tracks.is_empty() {
+            println!(
+                "WARNING: the {anim_label} has a Transform Node `{name}` with no Track at all."
+            );
+            continue;
+        }
+        if tracks.len() > 1 {
+            println!(
+                "WARNING: the {anim_label} has `{}` Tracks in the Transform Node `{name}`. All of them will be spliced.",
+                tracks.len()
+            );
+        }
+        for track in tracks
+            .iter()
+            .filter(|track| track.flags.track_type != TrackTypeV2::Transform)
+        {
+            println!(
+                "WARNING: the {anim_label} is poorly formatted and has vis or mat data in the Transform Node `{name}`, Track=`{}`, type={:?}.",
+                track.name.to_string_lossy(),
+                track.flags.track_type
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wifisafe_common::test_anims::*;
+
+    /// A one track anim with the track's data range replaced.
+    fn anim_with_range(data_offset: u32, data_size: Option<u64>) -> Anim {
+        let mut anim = Anim::try_from(&anim_data(
+            1,
+            vec![group(
+                ssbh_data::anim_data::GroupType::Transform,
+                vec![transform_node("Hip", 0.0)],
+            )],
+        ))
+        .unwrap();
+        if let Anim::V21 { groups, .. } = &mut anim {
+            let track = &mut groups.elements[0].nodes.elements[0].tracks.elements[0];
+            track.data_offset = data_offset;
+            if let Some(data_size) = data_size {
+                track.data_size = data_size;
+            }
+        }
+        anim
+    }
+
+    fn buffer_len(anim: &Anim) -> u64 {
+        match anim {
+            Anim::V20 { buffer, .. } | Anim::V21 { buffer, .. } => buffer.elements.len() as u64,
+            Anim::V12 { .. } => 0,
+        }
+    }
+
+    #[test]
+    fn encoded_anim_is_in_range() {
+        assert!(check_track_ranges(&anim_with_range(0, None), "reference anim").is_ok());
+    }
+
+    #[test]
+    fn data_past_the_end_of_the_buffer_is_an_error() {
+        let anim = anim_with_range(0, None);
+        let len = buffer_len(&anim);
+        assert!(check_track_ranges(&anim_with_range(0, Some(len)), "reference anim").is_ok());
+        assert!(check_track_ranges(&anim_with_range(0, Some(len + 1)), "reference anim").is_err());
+        assert!(check_track_ranges(&anim_with_range(1, Some(len)), "reference anim").is_err());
+    }
+
+    #[test]
+    fn offset_past_the_end_of_the_buffer_is_an_error() {
+        let len = buffer_len(&anim_with_range(0, None));
+        let error = check_track_ranges(&anim_with_range(len as u32 + 1, Some(0)), "modified anim")
+            .unwrap_err();
+        assert!(error.to_string().contains("The modified anim has a track"));
+    }
+
+    #[test]
+    fn overflowing_range_is_an_error() {
+        assert!(check_track_ranges(&anim_with_range(1, Some(u64::MAX)), "reference anim").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use wifisafe_common::validation::{validate_anim, SafetyRating};
+
+use crate::diff::find_changed_vanilla_track;
+use crate::{do_batch_mode, BatchOptions, SpliceOptions};
+
+/// The modification time and size of every file in the folders, which is enough to notice an export.
+///
+/// A zip file given in place of a folder is checked as a whole.
+fn snapshot(dirs: &[&Path]) -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+    let file_snapshot = |path: PathBuf| {
+        let metadata = fs::metadata(&path).ok()?;
+        metadata
+            .is_file()
+            .then(|| (path, metadata.modified().ok(), metadata.len()))
+    };
+    let mut files = dirs
+        .iter()
+        .flat_map(|dir| match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| file_snapshot(entry.path()))
+                .collect(),
+            Err(_) => file_snapshot(dir.to_path_buf())
+                .into_iter()
+                .collect::<Vec<_>>(),
+        })
+        .collect::<Vec<_>>();
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    files
+}
+
+/// Rates a spliced anim with the validator's rules, then checks that its vanilla Transform tracks are byte for byte the same.
+fn rate_spliced_anim(reference_anim_path: &Path, spliced_anim_path: &Path) -> SafetyRating {
+    match validate_anim(reference_anim_path, spliced_anim_path, None) {
+        SafetyRating::Safe => {
+            match find_changed_vanilla_track(reference_anim_path, spliced_anim_path) {
+                Ok(None) => SafetyRating::Safe,
+                Ok(Some(reason)) => SafetyRating::Unsafe(reason),
+                Err(e) => SafetyRating::Warning(format!("{e:#}")),
+            }
+        }
+        rating => rating,
+    }
+}
+
+/// Runs batch mode whenever a file in the reference or modified folders changes, and validates every anim it splices.
+///
+/// Only the anims whose inputs changed are spliced again, since the build cache is always used. Runs until the process is stopped.
+pub fn watch(
+    batch_reference_dir: &Path,
+    batch_modified_dirs: &[PathBuf],
+    batch_output_dir: &Path,
+    batch_options: &BatchOptions,
+    interval: Duration,
+    options: &SpliceOptions,
+) -> Result<()> {
+    let watched_dirs = std::iter::once(batch_reference_dir)
+        .chain(batch_modified_dirs.iter().map(PathBuf::as_path))
+        .collect::<Vec<_>>();
+
+    let batch_options = BatchOptions {
+        incremental: true,
+        ..batch_options.clone()
+    };
+
+    let mut last_snapshot = None;
+    loop {
+        let current_snapshot = snapshot(&watched_dirs);
+        if last_snapshot.as_ref() != Some(&current_snapshot) {
+            match do_batch_mode(
+                batch_reference_dir,
+                batch_modified_dirs,
+                batch_output_dir,
+                &batch_options,
+                options,
+            ) {
+                Ok(report) => {
+                    for entry in &report.spliced {
+                        match rate_spliced_anim(&entry.reference, &entry.output) {
+                            SafetyRating::Safe => println!("SAFE: Anim={:?}", entry.anim),
+                            SafetyRating::Unsafe(reason) => {
+                                println!("UNSAFE: Anim={:?}, reason=`{reason}`", entry.anim)
+                            }
+                            SafetyRating::Warning(reason) => {
+                                println!("WARNING: Anim={:?}, reason=`{reason}`", entry.anim)
+                            }
+                        }
+                    }
+                }
+                Err(e) => println!(
+                    "An error {e:#} happened splicing the folders, waiting for the next change."
+                ),
+            }
+            println!("Watching for changes, press Ctrl+C to stop...");
+            last_snapshot = Some(current_snapshot);
+        }
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssbh_data::anim_data::GroupType;
+    use wifisafe_common::test_anims::*;
+
+    fn rate(reference: &AnimData, spliced: &AnimData) -> SafetyRating {
+        let dir = TestDir::new();
+        let reference_path = dir.write_anim("reference.nuanmb", reference);
+        let spliced_path = dir.write_anim("spliced.nuanmb", spliced);
+        rate_spliced_anim(&reference_path, &spliced_path)
+    }
+
+    fn vanilla_anim() -> AnimData {
+        anim_data(
+            0,
+            vec![group(
+                GroupType::Transform,
+                vec![transform_node("Hip", 0.0), transform_node("ArmL", 1.0)],
+            )],
+        )
+    }
+
+    #[test]
+    fn splice_that_keeps_vanilla_bones_is_safe() {
+        let mut spliced = vanilla_anim();
+        spliced.groups.push(group(
+            GroupType::Visibility,
+            vec![visibility_node("Sword", false)],
+        ));
+        spliced.groups[0].nodes.push(transform_node("SwordB", 5.0));
+        assert!(matches!(
+            rate(&vanilla_anim(), &spliced),
+            SafetyRating::Safe
+        ));
+    }
+
+    #[test]
+    fn changed_vanilla_bone_is_unsafe() {
+        let mut spliced = vanilla_anim();
+        spliced.groups[0].nodes[1] = transform_node("ArmL", 2.0);
+        assert!(matches!(
+            rate(&vanilla_anim(), &spliced),
+            SafetyRating::Unsafe(_)
+        ));
+    }
+
+    #[test]
+    fn changed_vanilla_track_flags_are_unsafe() {
+        // The decoded values still match, so only the byte comparison notices.
+        let mut spliced = vanilla_anim();
+        spliced.groups[0].nodes[0].tracks[0].compensate_scale = true;
+        assert!(matches!(
+            rate(&vanilla_anim(), &spliced),
+            SafetyRating::Unsafe(_)
+        ));
+    }
+
+    #[test]
+    fn missing_vanilla_bone_is_unsafe() {
+        let mut spliced = vanilla_anim();
+        spliced.groups[0].nodes.remove(0);
+        assert!(matches!(
+            rate(&vanilla_anim(), &spliced),
+            SafetyRating::Unsafe(_)
+        ));
+    }
+
+    #[test]
+    fn different_final_frame_index_is_unsafe() {
+        let mut spliced = vanilla_anim();
+        spliced.final_frame_index += 1.0;
+        assert!(matches!(
+            rate(&vanilla_anim(), &spliced),
+            SafetyRating::Unsafe(_)
+        ));
+    }
+
+    #[test]
+    fn unreadable_spliced_anim_is_not_safe() {
+        let dir = TestDir::new();
+        let reference_path = dir.write_anim("reference.nuanmb", &vanilla_anim());
+        let spliced_path = dir.path.join("spliced.nuanmb");
+        fs::write(&spliced_path, b"not an anim").unwrap();
+        assert!(matches!(
+            rate_spliced_anim(&reference_path, &spliced_path),
+            SafetyRating::Warning(_)
+        ));
+    }
+}
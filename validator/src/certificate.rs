@@ -0,0 +1,258 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::manifest::{transform_bone_hashes, validate_anim_against_manifest, AnimEntry};
+use crate::{is_victory_anim, Reference};
+use wifisafe_common::archive::{is_nuanmb_path, read_anim_dir, split_zip_path};
+use wifisafe_common::hash::{hash_file, sha256_hex};
+use wifisafe_common::validation::{get_ancestors, SafetyRating, WorldSpaceCheck};
+
+#[derive(Serialize, Deserialize)]
+struct CertifiedAnim {
+    /// The hash of the whole modified anim file.
+    anim_hash: String,
+    /// The vanilla anim, so the anim can be validated again if it changed.
+    vanilla_anim: AnimEntry,
+    /// The protected bones and their ancestors, which also have to match if the vanilla anim doesn't have them.
+    protected_bones: BTreeSet<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CertificateContents {
+    validator_version: String,
+    /// The anims of the mod by their path in the mod folder or zip file.
+    anims: BTreeMap<String, CertifiedAnim>,
+}
+
+impl CertificateContents {
+    fn hash(&self) -> Result<String> {
+        let json = serde_json::to_string(self).context("could not serialize the certificate")?;
+        Ok(sha256_hex(json.as_bytes()))
+    }
+}
+
+/// A record that every anim of a mod passed validation, which can be checked again without the vanilla anims.
+///
+/// The checksum only catches a certificate that was damaged, like a truncated download. Anyone who edits
+/// a certificate can write a new checksum, so it proves neither who wrote it nor that it wasn't changed.
+#[derive(Serialize, Deserialize)]
+pub struct Certificate {
+    #[serde(flatten)]
+    contents: CertificateContents,
+    /// The SHA-256 of everything else in the certificate.
+    checksum: String,
+}
+
+impl Certificate {
+    /// Certifies the validated anims with their vanilla anims. The protected bones of `world_space_check` are recorded
+    /// along with their ancestors.
+    pub fn new(
+        reference: &Reference,
+        safe_anim_paths: &[PathBuf],
+        world_space_check: Option<&WorldSpaceCheck>,
+    ) -> Result<Self> {
+        let mut anims = BTreeMap::new();
+        for path in safe_anim_paths {
+            let file_name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let vanilla_anim = match reference {
+                Reference::Folder { anim_paths, .. } => {
+                    let reference_anim_path = anim_paths
+                        .iter()
+                        .find(|reference_path| reference_path.file_name() == path.file_name())
+                        .with_context(|| format!("no vanilla anim was found for {file_name:?}"))?;
+                    AnimEntry::from_file(reference_anim_path)?
+                }
+                Reference::Manifest(manifest) => manifest
+                    .get(&file_name)
+                    .with_context(|| format!("no vanilla anim was found for {file_name:?}"))?
+                    .clone(),
+            };
+            let protected_bones = match world_space_check {
+                Some(check) => check
+                    .protected_bones_for(path)?
+                    .iter()
+                    .flat_map(|bone| {
+                        std::iter::once(bone.as_str())
+                            .chain(get_ancestors(&check.skeleton, bone))
+                            .map(str::to_string)
+                    })
+                    .collect(),
+                None => BTreeSet::new(),
+            };
+            anims.insert(
+                anim_key(path),
+                CertifiedAnim {
+                    anim_hash: hash_file(path)?,
+                    vanilla_anim,
+                    protected_bones,
+                },
+            );
+        }
+
+        let contents = CertificateContents {
+            validator_version: env!("CARGO_PKG_VERSION").to_string(),
+            anims,
+        };
+        Ok(Self {
+            checksum: contents.hash()?,
+            contents,
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("could not read the certificate `{}`", path.display()))?;
+        let certificate: Self = serde_json::from_str(&json)
+            .with_context(|| format!("could not parse the certificate `{}`", path.display()))?;
+        if certificate.contents.hash()? != certificate.checksum {
+            return Err(anyhow::format_err!(
+                "The certificate `{}` doesn't match its checksum, so it's damaged or was edited by hand!",
+                path.display()
+            ));
+        }
+        Ok(certificate)
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(self).context("could not serialize the certificate")?;
+        fs::write(path, json)
+            .with_context(|| format!("could not write the certificate to `{}`", path.display()))
+    }
+
+    pub fn anim_count(&self) -> usize {
+        self.contents.anims.len()
+    }
+}
+
+/// The path of an anim inside its zip file, or its file name in a folder, which tells apart anims of different slots.
+fn anim_key(path: &Path) -> String {
+    match split_zip_path(path) {
+        Some((_, entry)) => entry,
+        None => path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+    }
+}
+
+/// Checks the anims of a mod folder against a certificate, printing every anim that doesn't match it.
+///
+/// An anim that changed since it was certified is validated again against its vanilla anim, and is only accepted
+/// with a warning if it's still safe.
+/// Returns an error if any anim is unsafe, isn't in the certificate, or is missing from the mod folder.
+pub fn verify_certificate(certificate: &Certificate, modified_dir: &Path) -> Result<()> {
+    if certificate.contents.validator_version != env!("CARGO_PKG_VERSION") {
+        println!(
+            "WARNING: The certificate was written by validator version `{}`, but this is version `{}`.",
+            certificate.contents.validator_version,
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+
+    let modified_anim_paths = read_anim_dir(modified_dir, is_nuanmb_path)
+        .context("could not read the modified folder")?;
+
+    let mut verified_count = 0;
+    let mut warning_count = 0;
+    let mut unsafe_count = 0;
+    let mut uncertified_count = 0;
+    let mut missing_count = 0;
+    let mut skip_count = 0;
+
+    for modified_anim_path in &modified_anim_paths {
+        let file_name = modified_anim_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if is_victory_anim(&file_name) {
+            skip_count += 1;
+            continue;
+        }
+
+        let key = anim_key(modified_anim_path);
+        let Some(certified_anim) = certificate.contents.anims.get(&key) else {
+            println!("UNSAFE: Anim={key:?}, reason=`The anim isn't in the certificate`");
+            uncertified_count += 1;
+            continue;
+        };
+
+        if hash_file(modified_anim_path)? == certified_anim.anim_hash {
+            verified_count += 1;
+            continue;
+        }
+
+        match revalidate(certified_anim, modified_anim_path) {
+            SafetyRating::Safe => {
+                println!("WARNING: Anim={key:?}, reason=`The anim changed since it was certified, but it's still safe against the vanilla anim`");
+                warning_count += 1;
+            }
+            SafetyRating::Unsafe(reason) | SafetyRating::Warning(reason) => {
+                println!("UNSAFE: Anim={key:?}, reason=`The anim changed since it was certified: {reason}`");
+                unsafe_count += 1;
+            }
+        }
+    }
+
+    let modified_keys = modified_anim_paths
+        .iter()
+        .map(|path| anim_key(path))
+        .collect::<BTreeSet<_>>();
+    for key in certificate.contents.anims.keys() {
+        if !modified_keys.contains(key) {
+            println!(
+                "UNSAFE: Anim={key:?}, reason=`The certified anim is missing from the mod folder`"
+            );
+            missing_count += 1;
+        }
+    }
+
+    println!("Total Modified Anims: {}", modified_anim_paths.len());
+    println!("Verified Count: {verified_count}");
+    println!("Unsafe Count: {unsafe_count}");
+    println!("Uncertified Count: {uncertified_count}");
+    println!("Missing Count: {missing_count}");
+    println!("Warning Count: {warning_count}");
+    println!("Skip Count: {skip_count}");
+    if unsafe_count > 0 || uncertified_count > 0 || missing_count > 0 {
+        return Err(anyhow::format_err!(
+            "The mod folder doesn't match the certificate!"
+        ));
+    }
+    Ok(())
+}
+
+/// Validates a changed anim against its certified vanilla anim, with the rules of a manifest.
+fn revalidate(certified_anim: &CertifiedAnim, modified_anim_path: &Path) -> SafetyRating {
+    let rating =
+        validate_anim_against_manifest(&certified_anim.vanilla_anim, modified_anim_path, None);
+    if !matches!(rating, SafetyRating::Safe) {
+        return rating;
+    }
+
+    // Every vanilla bone was checked, but a protected bone the vanilla anim doesn't have must not be added either.
+    let bone_hashes = match transform_bone_hashes(modified_anim_path) {
+        Ok(bone_hashes) => bone_hashes,
+        Err(e) => return SafetyRating::Warning(format!("{e:#}")),
+    };
+    let vanilla_bone_hashes = certified_anim.vanilla_anim.bone_hashes();
+    match certified_anim
+        .protected_bones
+        .iter()
+        .find(|name| bone_hashes.get(*name) != vanilla_bone_hashes.get(*name))
+    {
+        Some(name) => SafetyRating::Unsafe(format!(
+            "the protected bone or ancestor `{name}` no longer matches the vanilla anim!"
+        )),
+        None => SafetyRating::Safe,
+    }
+}
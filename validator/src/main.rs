@@ -1,162 +1,143 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
 use ssbh_data::prelude::*;
-use std::collections::HashMap;
-use std::iter::zip;
-use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
-use ssbh_data::anim_data::{GroupType, NodeData, TrackValues};
+
+mod certificate;
+mod manifest;
+#[cfg(test)]
+mod tests;
+mod versions;
+
+use wifisafe_common::acmd::HitboxBones;
+use wifisafe_common::archive::{is_json_path, is_nuanmb_path, is_same_path, read_anim_dir};
+use wifisafe_common::validation::{validate_anim, SafetyRating, WorldSpaceCheck};
+use certificate::{verify_certificate, Certificate};
+use manifest::{validate_anim_against_manifest, ReferenceManifest};
+use versions::{validate_versions, ReferenceVersion, ReferenceVersionArg};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about=None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(short = 'r', long = "reference_folder")]
     reference_folder: Option<PathBuf>,
     #[arg(short = 'm', long = "modified_folder")]
     modified_folder: Option<PathBuf>,
+    /// The fighter's `.nusktb`. When given, the protected bones are also checked for changes to any of their ancestors
+    #[arg(short = 's', long = "skeleton")]
+    skeleton: Option<PathBuf>,
+    /// The bones to check in world space, like hitbox bones (requires -s). Defaults to every bone in the vanilla anim
+    #[arg(long = "protected_bones", num_args = 1..)]
+    protected_bones: Vec<String>,
+    /// Also protect every bone with a hitbox, grab box, search box or hurtbox in these dumped fighter scripts, a file or folder (requires -s)
+    #[arg(long = "acmd_dump")]
+    acmd_dump: Option<PathBuf>,
+    /// The fighter of the anims in --acmd_dump, like `mario`. Only needed when the scripts have several fighters and the anims aren't in a `fighter/<name>` folder
+    #[arg(long = "fighter")]
+    fighter: Option<String>,
+    /// Validate against a manifest from --write_manifest instead of a reference folder
+    #[arg(long = "reference_manifest")]
+    reference_manifest: Option<PathBuf>,
+    /// Write a manifest of the reference folder's anims to this file instead of validating
+    #[arg(long = "write_manifest")]
+    write_manifest: Option<PathBuf>,
+    /// When every anim is safe, write a certificate of the mod with the vanilla hash of each bone and the protected bones to this file
+    #[arg(long = "certificate")]
+    certificate: Option<PathBuf>,
+    /// Validate against several game versions instead of one reference, like `13.0.1=vanilla_13_0_1`. The reference can be a folder, zip or manifest
+    #[arg(long = "reference_version")]
+    reference_versions: Vec<ReferenceVersionArg>,
 }
 
-enum SafetyRating {
-    Safe,
-    Unsafe(String),
-    Warning(String),
+#[derive(Subcommand)]
+enum Command {
+    /// Check a mod folder against a certificate written with --certificate, without the vanilla anims
+    VerifyCertificate {
+        #[arg(short = 'c', long = "certificate")]
+        certificate: PathBuf,
+        #[arg(short = 'm', long = "modified_folder")]
+        modified_folder: PathBuf,
+    },
 }
 
-fn get_group_by_type(
-    anim_data: &AnimData,
-    group_type: ssbh_data::anim_data::GroupType,
-) -> Option<&ssbh_data::anim_data::GroupData> {
-    for group in &anim_data.groups {
-        if group.group_type == group_type {
-            return Some(&group);
-        }
-    }
-    None
+/// Where the vanilla anims to validate against come from.
+enum Reference {
+    Folder {
+        dir: PathBuf,
+        anim_paths: Vec<PathBuf>,
+    },
+    /// The hashes of the vanilla anims, so the anims themselves aren't needed.
+    Manifest(ReferenceManifest),
 }
 
-fn validate_anim(reference_anim_path: &PathBuf, modified_anim_path: &PathBuf) -> SafetyRating {
-    let reference_anim = match ssbh_data::anim_data::AnimData::from_file(&reference_anim_path) {
-        Ok(anim) => anim,
-        Err(e) => {
-            return SafetyRating::Warning(format!(
-                "Reference anim could not be opened by ssbh_data, error=`{}`",
-                e
+impl Reference {
+    /// Reads a reference folder or zip. Manifests are read with [ReferenceManifest::from_file] instead.
+    fn from_path(path: &Path) -> Result<Self> {
+        if is_json_path(path) {
+            return Err(anyhow::format_err!(
+                "`{}` is a manifest, not a reference folder or zip! Use --reference_manifest for manifests.",
+                path.display()
             ));
         }
-    };
+        Ok(Self::Folder {
+            dir: path.to_path_buf(),
+            anim_paths: read_anim_dir(path, is_nuanmb_path).context("could not read the reference folder")?,
+        })
+    }
 
-    let modified_anim = match ssbh_data::anim_data::AnimData::from_file(&modified_anim_path) {
-        Ok(anim) => anim,
-        Err(e) => {
-            return SafetyRating::Warning(format!(
-                "Modified anim could not be opened by ssbh_data, error=`{}`",
-                e
-            ));
+    /// Validates the modified anim against the vanilla anim with the same name, or returns `None` if there isn't one.
+    fn validate(&self, modified_anim_path: &Path, world_space_check: Option<&WorldSpaceCheck>) -> Option<SafetyRating> {
+        match self {
+            Self::Folder { anim_paths, .. } => anim_paths
+                .iter()
+                .find(|&p| p.file_name() == modified_anim_path.file_name())
+                .map(|matching_vanilla_anim_path| validate_anim(matching_vanilla_anim_path, modified_anim_path, world_space_check)),
+            Self::Manifest(manifest) => manifest
+                .get(&modified_anim_path.file_name().unwrap_or_default().to_string_lossy())
+                .map(|entry| validate_anim_against_manifest(entry, modified_anim_path, world_space_check)),
         }
-    };
-
-    if reference_anim.final_frame_index != modified_anim.final_frame_index {
-        return SafetyRating::Unsafe(
-            format!(
-                "The modifed anim has a final_frame_index of `{}`, while the matching vanilla anim has a final_frame_index of `{}`",
-                modified_anim.final_frame_index,
-                reference_anim.final_frame_index
-            )
-        );
     }
+}
 
-    let (ref_trans_group, mod_trans_group) = match (get_group_by_type(&reference_anim, GroupType::Transform), get_group_by_type(&modified_anim, GroupType::Transform)){
-        (Some(ref_group), Some(mod_group)) => {(ref_group, mod_group)},
-        (Some(_ref_group), None) => {return SafetyRating::Unsafe("The reference anim has a transform group, but the modified group has no transform group!".to_string())},
-        (None, Some(_mod_group)) => {return SafetyRating::Warning("The modified anim has transform data, but the vanilla anim had none! As long as you're 100% sure you didn't mess with any vanilla hitbox/hurtbox bones, this can still be ok.".to_string())},
-        (None, None) => {return SafetyRating::Safe}
-    };
-
-    let mod_nodes_by_name: HashMap<String, &NodeData> = mod_trans_group.nodes.iter().map(|x| (x.name.clone(), x)).collect();
-
-    // For the Transform group, each Node corresponds to a bone.
-    // Each bone Node will only have one Track, which is it's transform values.
-    for reference_node in &ref_trans_group.nodes {
-        let reference_values = match reference_node.tracks.get(0) {
-            None => {
-                println!("The reference anim {:?} has a Node for bone `{}` with no transform Track at all! Skipping this bone..", reference_anim_path.file_name().unwrap_or_default(), reference_node.name);
-                continue;
-             }
-             Some(track) => {
-                match &track.values{
-                    TrackValues::Transform(values) => {values},
-                    _ => {
-                        /* Some vanilla anims like
-                        fighter/kirby/motion/jackbody/c00/jackd00specialairnrandomend.nuanmb
-                        are poorly formatted like this.
-                        */
-                        println!("The reference anim `{:?}` is poorly formatted and has vis or mat data in the transform data. Skipping that track...", reference_anim_path.file_name().unwrap_or_default());
-                        continue;
-                    }
-                }
-             },
-        };
-
-        let modified_node = match mod_nodes_by_name.get(&reference_node.name){
-            Some(node) => {node},
-            None => {return SafetyRating::Unsafe(format!("Modified anim missing transform node `{}`", reference_node.name))}
-        };
-
-        let modified_values = match modified_node.tracks.get(0) {
-            None => {return SafetyRating::Unsafe(format!("The modified anim is missing the Transform Track for Node `{}`", modified_node.name))},
-            Some(track) => match &track.values{
-                TrackValues::Transform(values) => {values},
-                _ => {return SafetyRating::Unsafe(format!("The modified anim is poorly formatted and has vis or mat data instead of transform data for Node `{}`", modified_node.name))}
-            }
-        };
-
-        if reference_values.len() != modified_values.len(){
-            return SafetyRating::Unsafe(format!(
-                "The Node `{}` has different amount of values in the vanilla vs the modified! Vanilla=`{}`, Modified=`{}`", 
-                modified_node.name,
-                reference_values.len(),
-                modified_values.len()))
-        }
+/// The outcome of [validate_dirs].
+struct ValidationSummary {
+    safe_anim_paths: Vec<PathBuf>,
+    unsafe_count: usize,
+    warning_count: usize,
+}
 
-        for (index, (reference_value, modified_value)) in zip(reference_values.iter(), modified_values.iter()).enumerate(){
-            if reference_value != modified_value {
-                return SafetyRating::Unsafe(
-                    format!(
-                        "The Node `{}` at frame `{}` has differing values! Vanilla=`{:?}`, Modified=`{:?}`",
-                        modified_node.name,
-                        index,
-                        reference_value,
-                        modified_value,
-                    )
-                );
-            }
+/// Warns about the bones with collision that aren't in the skeleton, since scripts write bone names in lowercase.
+fn warn_about_unknown_script_bones(hitbox_bones: &HitboxBones, skeleton: &SkelData) {
+    for hitbox_bone in hitbox_bones.all_bones() {
+        if !skeleton.bones.iter().any(|bone| bone.name.eq_ignore_ascii_case(hitbox_bone)) {
+            println!("WARNING: The bone `{hitbox_bone}` from the scripts isn't in the skeleton, so it can't be protected.");
         }
     }
+}
 
-    SafetyRating::Safe
+/// Victory screen anims are never checked, since they can't affect online play.
+fn is_victory_anim(file_name: &str) -> bool {
+    file_name.starts_with("j02")
 }
 
-fn validate_dirs(reference_dir: &PathBuf, modified_dir: &PathBuf) -> Result<()> {
-    if reference_dir == modified_dir {
+fn validate_dirs(
+    reference: &Reference,
+    modified_dir: &Path,
+    world_space_check: Option<&WorldSpaceCheck>,
+) -> Result<ValidationSummary> {
+    if matches!(reference, Reference::Folder { dir, .. } if is_same_path(dir, modified_dir)) {
         return Err(anyhow::format_err!(
             "Specified 'Reference' and 'Modified' folders are the same folders!"
         ));
     }
-    let reference_anim_paths = fs::read_dir(reference_dir)
-        .unwrap()
-        .filter_map(|dir_entry| dir_entry.ok())
-        .map(|dir_entry| dir_entry.path())
-        .filter(|path| path.extension().unwrap().eq("nuanmb"))
-        .collect::<Vec<_>>();
-
-    let modified_anim_paths = fs::read_dir(modified_dir)
-        .unwrap()
-        .filter_map(|dir_entry| dir_entry.ok())
-        .map(|dir_entry| dir_entry.path())
-        .filter(|path| path.extension().unwrap().eq("nuanmb"))
-        .collect::<Vec<_>>();
 
+    let modified_anim_paths = read_anim_dir(modified_dir, is_nuanmb_path)
+        .context("could not read the modified folder")?;
+
+    let mut safe_anim_paths = Vec::new();
     let mut warning_count = 0;
     let mut unsafe_count = 0;
     let mut skip_count = 0;
@@ -167,28 +148,22 @@ fn validate_dirs(reference_dir: &PathBuf, modified_dir: &PathBuf) -> Result<()>
             .unwrap_or_default()
             .to_str()
             .unwrap_or_default();
-        if modified_anim_file_name.starts_with("j02") {
+        if is_victory_anim(modified_anim_file_name) {
             println!("SKIPPED: Skipping {modified_anim_file_name}, since it's name starts with `j02` and is a victory screen animation.");
             skip_count += 1;
             continue;
         }
 
-        let matching_vanilla_anim_path: PathBuf = match reference_anim_paths
-            .iter()
-            .find(|&p| p.file_name() == modified_anim_path.file_name())
-        {
-            Some(path) => path.clone(),
-            None => {
-                println!(
-                    "WARNING: Can't validate modified file {modified_anim_path:?}, no vanilla anim was found!"
-                );
-                warning_count += 1;
-                continue;
-            }
+        let Some(rating) = reference.validate(modified_anim_path, world_space_check) else {
+            println!(
+                "WARNING: Can't validate modified file {modified_anim_path:?}, no vanilla anim was found!"
+            );
+            warning_count += 1;
+            continue;
         };
 
-        match validate_anim(&matching_vanilla_anim_path, &modified_anim_path) {
-            SafetyRating::Safe => {}
+        match rating {
+            SafetyRating::Safe => safe_anim_paths.push(modified_anim_path.clone()),
             SafetyRating::Unsafe(msg) => {
                 println!(
                     "UNSAFE: Anim={:?}, reason=`{}`",
@@ -212,7 +187,11 @@ fn validate_dirs(reference_dir: &PathBuf, modified_dir: &PathBuf) -> Result<()>
     println!("Unsafe Count: {}", unsafe_count);
     println!("Warning Count: {}", warning_count);
     println!("Skip Count: {}", skip_count);
-    Ok(())
+    Ok(ValidationSummary {
+        safe_anim_paths,
+        unsafe_count,
+        warning_count,
+    })
 }
 
 fn main() -> Result<()> {
@@ -220,16 +199,126 @@ fn main() -> Result<()> {
 
     let args = Args::parse();
 
-    let reference_dir = args
-        .reference_folder
-        .expect("Reference Folder not provided!");
+    if let Some(Command::VerifyCertificate { certificate, modified_folder }) = &args.command {
+        println!("Now verifying, please wait...");
+        let certificate = Certificate::from_file(certificate)?;
+        let result = verify_certificate(&certificate, modified_folder);
+        println!("Done! elapsed time = {:?}!", start_time.elapsed());
+        return result;
+    }
+
+    if let Some(manifest_path) = args.write_manifest {
+        let reference_dir = args
+            .reference_folder
+            .context("--write_manifest needs the reference folder to write the manifest of, given with -r!")?;
+        println!("Now writing the manifest, please wait...");
+        let manifest = ReferenceManifest::from_dir(&reference_dir)
+            .context("could not read the reference folder")?;
+        manifest.write_to_file(&manifest_path)?;
+        println!("Wrote {} anims to the manifest {:?}!", manifest.anim_count(), manifest_path);
+        println!("Done! elapsed time = {:?}!", start_time.elapsed());
+        return Ok(());
+    }
 
     let modified_dir = args
         .modified_folder
-        .expect("Modified Folder not provided!");
+        .context("Modified Folder not provided!")?;
+
+    let skeleton = match &args.skeleton {
+        Some(skeleton_path) => Some(SkelData::from_file(skeleton_path).map_err(|e| {
+            anyhow::format_err!(
+                "could not read skeleton `{}`, error=`{e}`",
+                skeleton_path.display()
+            )
+        })?),
+        None => None,
+    };
+
+    match &skeleton {
+        Some(skeleton) => {
+            if let Some(missing) = args.protected_bones.iter().find(|name| !skeleton.bones.iter().any(|bone| &bone.name == *name)) {
+                return Err(anyhow::format_err!(
+                    "The protected bone `{missing}` isn't in the skeleton, so it can't be checked! Bone names are case sensitive."
+                ));
+            }
+        }
+        None if !args.protected_bones.is_empty() => {
+            return Err(anyhow::format_err!(
+                "--protected_bones needs the fighter's skeleton with -s, since the protected bones are only checked in world space!"
+            ));
+        }
+        None => (),
+    }
+
+    let hitbox_bones = match &args.acmd_dump {
+        Some(acmd_dump) => {
+            let Some(skeleton) = &skeleton else {
+                return Err(anyhow::format_err!(
+                    "--acmd_dump needs the fighter's skeleton with -s, to find the bones named in the scripts!"
+                ));
+            };
+            let hitbox_bones = HitboxBones::from_dump(acmd_dump)?;
+            hitbox_bones.print_summary();
+            warn_about_unknown_script_bones(&hitbox_bones, skeleton);
+            Some(hitbox_bones)
+        }
+        None if args.fighter.is_some() => {
+            return Err(anyhow::format_err!("--fighter only chooses the fighter of --acmd_dump!"));
+        }
+        None => None,
+    };
+
+    let world_space_check = skeleton.map(|skeleton| WorldSpaceCheck {
+        skeleton,
+        protected_bones: args.protected_bones.clone(),
+        hitbox_bones,
+        fighter: args.fighter.clone(),
+    });
+
+    if !args.reference_versions.is_empty() {
+        if args.reference_folder.is_some() || args.reference_manifest.is_some() || args.certificate.is_some() {
+            return Err(anyhow::format_err!(
+                "--reference_version can't be used with -r, --reference_manifest or --certificate!"
+            ));
+        }
+        let versions = args
+            .reference_versions
+            .iter()
+            .map(ReferenceVersion::from_arg)
+            .collect::<Result<Vec<_>>>()?;
+        println!("Now validating, please wait...");
+        let result = validate_versions(&versions, &modified_dir, world_space_check.as_ref());
+        println!("Done! elapsed time = {:?}!", start_time.elapsed());
+        return result;
+    }
+
+    let reference = match (args.reference_manifest, args.reference_folder) {
+        (Some(_), Some(_)) => {
+            return Err(anyhow::format_err!(
+                "--reference_manifest can't be used with -r, since only one reference is validated against!"
+            ));
+        }
+        (Some(manifest_path), None) => Reference::Manifest(ReferenceManifest::from_file(&manifest_path)?),
+        (None, Some(reference_dir)) => Reference::from_path(&reference_dir)?,
+        (None, None) => {
+            return Err(anyhow::format_err!(
+                "Reference Folder not provided! Give it with -r, or a manifest with --reference_manifest."
+            ));
+        }
+    };
 
     println!("Now validating, please wait...");
-    let result = validate_dirs(&reference_dir, &modified_dir);
+    let summary = validate_dirs(&reference, &modified_dir, world_space_check.as_ref())?;
+
+    if let Some(certificate_path) = args.certificate {
+        if summary.unsafe_count > 0 || summary.warning_count > 0 {
+            println!("Not writing a certificate, since not every anim is safe.");
+        } else {
+            let certificate = Certificate::new(&reference, &summary.safe_anim_paths, world_space_check.as_ref())?;
+            certificate.write_to_file(&certificate_path)?;
+            println!("Certified {} anims in {:?}!", certificate.anim_count(), certificate_path);
+        }
+    }
     println!("Done! elapsed time = {:?}!", start_time.elapsed());
-    result
+    Ok(())
 }
@@ -0,0 +1,316 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use ssbh_lib::formats::anim::{Group, GroupType, TrackTypeV2, TrackV2};
+use ssbh_lib::{prelude::*, SsbhArray, SsbhByteBuffer, SsbhString};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use wifisafe_common::anim::{find_node, track_data};
+use wifisafe_common::archive::{self, is_nuanmb_path, read_anim_dir};
+use wifisafe_common::hash::sha256_hex;
+use wifisafe_common::validation::{SafetyRating, WorldSpaceCheck};
+
+/// Everything about a Transform track that has to match the vanilla track, with its data replaced by a hash.
+///
+/// Where the data lives in the buffer isn't included, since it changes whenever bones are added.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackEntry {
+    name: String,
+    track_type: String,
+    compression_type: String,
+    frame_count: u32,
+    transform_flags: String,
+    data_size: u64,
+    data_hash: String,
+}
+
+impl TrackEntry {
+    /// Returns `None` if the track's data isn't inside the buffer.
+    fn new(track: &TrackV2, buffer: &SsbhByteBuffer) -> Option<Self> {
+        let data = track_data(track, buffer)?;
+        Some(Self {
+            name: track.name.to_string_lossy(),
+            track_type: format!("{:?}", track.flags.track_type),
+            compression_type: format!("{:?}", track.flags.compression_type),
+            frame_count: track.frame_count,
+            transform_flags: format!("{:?}", track.transform_flags),
+            data_size: track.data_size,
+            data_hash: sha256_hex(data),
+        })
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BoneEntry {
+    name: String,
+    track: TrackEntry,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AnimEntry {
+    final_frame_index: f32,
+    /// The vanilla Transform bones in their original order, or `None` if the anim has no Transform group.
+    transform_bones: Option<Vec<BoneEntry>>,
+}
+
+impl AnimEntry {
+    /// The vanilla data hash of every Transform bone by name.
+    pub fn bone_hashes(&self) -> BTreeMap<String, String> {
+        self.transform_bones
+            .iter()
+            .flatten()
+            .map(|bone| (bone.name.clone(), bone.track.data_hash.clone()))
+            .collect()
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let anim = archive::read_anim(path)?;
+        let (final_frame_index, groups, buffer) = anim_parts(&anim).ok_or_else(|| {
+            anyhow::format_err!(
+                "`{}` is a version 1.2 anim, which isn't supported",
+                path.display()
+            )
+        })?;
+
+        let transform_bones = transform_group(groups).map(|group| {
+            group
+                .nodes
+                .elements
+                .iter()
+                .filter_map(|node| {
+                    let name = node.name.to_string_lossy();
+                    // Like the full validation, vanilla bones without usable transform data are skipped.
+                    let Some(track) = node.tracks.elements.first() else {
+                        println!("The reference anim {:?} has a Node for bone `{name}` with no transform Track at all! Skipping this bone..", path.file_name().unwrap_or_default());
+                        return None;
+                    };
+                    if track.flags.track_type != TrackTypeV2::Transform {
+                        println!("The reference anim `{:?}` is poorly formatted and has vis or mat data in the transform data. Skipping that track...", path.file_name().unwrap_or_default());
+                        return None;
+                    }
+                    match TrackEntry::new(track, buffer) {
+                        Some(track) => Some(BoneEntry { name, track }),
+                        None => {
+                            println!("The reference anim {:?} has transform data for bone `{name}` outside of its buffer! Skipping this bone..", path.file_name().unwrap_or_default());
+                            None
+                        }
+                    }
+                })
+                .collect()
+        });
+
+        Ok(Self {
+            final_frame_index,
+            transform_bones,
+        })
+    }
+}
+
+/// The hashes of every vanilla anim in a reference folder, which is enough to validate against without the anims themselves.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ReferenceManifest {
+    /// The validator version that wrote the manifest.
+    version: String,
+    /// The anims by file name.
+    anims: BTreeMap<String, AnimEntry>,
+}
+
+impl ReferenceManifest {
+    pub fn from_dir(reference_dir: &Path) -> Result<Self> {
+        let mut manifest = Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            anims: BTreeMap::new(),
+        };
+        for path in read_anim_dir(reference_dir, is_nuanmb_path)? {
+            let file_name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            match AnimEntry::from_file(&path) {
+                Ok(entry) => {
+                    manifest.anims.insert(file_name, entry);
+                }
+                Err(e) => {
+                    println!("WARNING: Leaving {file_name:?} out of the manifest, reason=`{e:#}`")
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("could not read the manifest `{}`", path.display()))?;
+        serde_json::from_str(&json)
+            .with_context(|| format!("could not parse the manifest `{}`", path.display()))
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string(self).context("could not serialize the manifest")?;
+        fs::write(path, json)
+            .with_context(|| format!("could not write the manifest to `{}`", path.display()))
+    }
+
+    pub fn anim_count(&self) -> usize {
+        self.anims.len()
+    }
+
+    pub fn get(&self, file_name: &str) -> Option<&AnimEntry> {
+        self.anims.get(file_name)
+    }
+}
+
+fn anim_parts(anim: &Anim) -> Option<(f32, &SsbhArray<Group>, &SsbhByteBuffer)> {
+    match anim {
+        Anim::V20 {
+            final_frame_index,
+            groups,
+            buffer,
+            ..
+        }
+        | Anim::V21 {
+            final_frame_index,
+            groups,
+            buffer,
+            ..
+        } => Some((*final_frame_index, groups, buffer)),
+        Anim::V12 { .. } => None,
+    }
+}
+
+/// The data hash of every Transform bone by name, like [AnimEntry::bone_hashes] but for any anim.
+///
+/// Bones without readable transform data are left out.
+pub fn transform_bone_hashes(path: &Path) -> Result<BTreeMap<String, String>> {
+    let anim = archive::read_anim(path)?;
+    let (_, groups, buffer) = anim_parts(&anim).ok_or_else(|| {
+        anyhow::format_err!(
+            "`{}` is a version 1.2 anim, which isn't supported",
+            path.display()
+        )
+    })?;
+    Ok(transform_group(groups)
+        .into_iter()
+        .flat_map(|group| &group.nodes.elements)
+        .filter_map(|node| {
+            let track = node.tracks.elements.first()?;
+            if track.flags.track_type != TrackTypeV2::Transform {
+                return None;
+            }
+            let track = TrackEntry::new(track, buffer)?;
+            Some((node.name.to_string_lossy(), track.data_hash))
+        })
+        .collect())
+}
+
+fn transform_group(groups: &SsbhArray<Group>) -> Option<&Group> {
+    groups
+        .elements
+        .iter()
+        .find(|group| group.group_type == GroupType::Transform)
+}
+
+/// Whether a bone is exactly the vanilla bone, or missing from both anims.
+fn bone_matches(
+    reference_bones: &[BoneEntry],
+    mod_trans_group: &Group,
+    buffer: &SsbhByteBuffer,
+    name: &str,
+) -> bool {
+    let reference_track = reference_bones
+        .iter()
+        .find(|bone| bone.name == name)
+        .map(|bone| &bone.track);
+    let modified_track = find_node(mod_trans_group, &SsbhString::from(name))
+        .and_then(|node| node.tracks.elements.first())
+        .and_then(|track| TrackEntry::new(track, buffer));
+    reference_track == modified_track.as_ref()
+}
+
+/// Validates a modified anim against a vanilla anim's manifest entry.
+///
+/// Only hashes are stored, so a vanilla bone is only safe if its track data is byte for byte the vanilla data,
+/// even if re-encoding it would give the same values.
+pub fn validate_anim_against_manifest(
+    reference: &AnimEntry,
+    modified_anim_path: &Path,
+    world_space_check: Option<&WorldSpaceCheck>,
+) -> SafetyRating {
+    let modified_anim = match archive::read_anim(modified_anim_path) {
+        Ok(anim) => anim,
+        Err(e) => {
+            return SafetyRating::Warning(format!(
+                "Modified anim could not be opened by ssbh_lib, error=`{e:#}`"
+            ));
+        }
+    };
+    let Some((final_frame_index, groups, buffer)) = anim_parts(&modified_anim) else {
+        return SafetyRating::Warning(
+            "The modified anim is a version 1.2 anim, which can't be checked against a manifest"
+                .to_string(),
+        );
+    };
+
+    if reference.final_frame_index != final_frame_index {
+        return SafetyRating::Unsafe(format!(
+            "The modified anim has a final_frame_index of `{}`, while the matching vanilla anim has a final_frame_index of `{}`",
+            final_frame_index, reference.final_frame_index
+        ));
+    }
+
+    let (reference_bones, mod_trans_group) = match (&reference.transform_bones, transform_group(groups)) {
+        (Some(reference_bones), Some(mod_group)) => (reference_bones, mod_group),
+        (Some(_), None) => return SafetyRating::Unsafe("The reference anim has a transform group, but the modified group has no transform group!".to_string()),
+        (None, Some(_)) => return SafetyRating::Warning("The modified anim has transform data, but the vanilla anim had none! As long as you're 100% sure you didn't mess with any vanilla hitbox/hurtbox bones, this can still be ok.".to_string()),
+        (None, None) => return SafetyRating::Safe,
+    };
+
+    for reference_bone in reference_bones {
+        let Some(modified_node) = find_node(
+            mod_trans_group,
+            &SsbhString::from(reference_bone.name.as_str()),
+        ) else {
+            return SafetyRating::Unsafe(format!(
+                "Modified anim missing transform node `{}`",
+                reference_bone.name
+            ));
+        };
+        let Some(modified_track) = modified_node.tracks.elements.first() else {
+            return SafetyRating::Unsafe(format!(
+                "The modified anim is missing the Transform Track for Node `{}`",
+                reference_bone.name
+            ));
+        };
+        let Some(modified_track) = TrackEntry::new(modified_track, buffer) else {
+            return SafetyRating::Unsafe(format!(
+                "The modified anim has transform data for Node `{}` outside of its buffer",
+                reference_bone.name
+            ));
+        };
+
+        if modified_track.data_hash != reference_bone.track.data_hash {
+            return SafetyRating::Unsafe(format!(
+                "The Node `{}` has different transform data than the vanilla anim in the manifest!",
+                reference_bone.name
+            ));
+        }
+        if modified_track != reference_bone.track {
+            return SafetyRating::Unsafe(format!(
+                "The Node `{}` has a different track layout in the vanilla vs the modified! Vanilla=`{:?}`, Modified=`{:?}`",
+                reference_bone.name, reference_bone.track, modified_track
+            ));
+        }
+    }
+
+    if let Some(world_space_check) = world_space_check {
+        return world_space_check.validate(
+            modified_anim_path,
+            reference_bones.iter().map(|bone| bone.name.as_str()),
+            |name| bone_matches(reference_bones, mod_trans_group, buffer, name),
+        );
+    }
+
+    SafetyRating::Safe
+}
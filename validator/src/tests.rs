@@ -0,0 +1,587 @@
+use crate::certificate::{verify_certificate, Certificate};
+use crate::manifest::{validate_anim_against_manifest, ReferenceManifest};
+use crate::versions::{rate_versions, validate_versions, ReferenceVersion, ReferenceVersionArg};
+use crate::{validate_dirs, Reference};
+use ssbh_data::anim_data::{GroupData, GroupType, NodeData};
+use ssbh_data::skel_data::{BoneData, SkelData};
+use ssbh_lib::formats::skel::BillboardType;
+use std::fs;
+use wifisafe_common::archive::{is_nuanmb_path, read_anim_dir, write_zip};
+use wifisafe_common::test_anims::*;
+use wifisafe_common::validation::{validate_anim, SafetyRating, WorldSpaceCheck};
+
+fn transform_group(nodes: Vec<NodeData>) -> GroupData {
+    group(GroupType::Transform, nodes)
+}
+
+/// Writes both anims to a temporary folder and validates the modified anim against the reference.
+fn validate(reference: &AnimData, modified: &AnimData) -> SafetyRating {
+    let dir = TestDir::new();
+    let reference_path = dir.write_anim("reference.nuanmb", reference);
+    let modified_path = dir.write_anim("modified.nuanmb", modified);
+    validate_anim(&reference_path, &modified_path, None)
+}
+
+#[test]
+fn identical_anims_are_safe() {
+    for minor_version in [0, 1] {
+        let anim = anim_data(
+            minor_version,
+            vec![transform_group(vec![
+                transform_node("Hip", 0.0),
+                transform_node("Waist", 1.0),
+            ])],
+        );
+        assert!(matches!(validate(&anim, &anim), SafetyRating::Safe));
+    }
+}
+
+#[test]
+fn added_bones_are_safe() {
+    let reference = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let modified = anim_data(
+        1,
+        vec![transform_group(vec![
+            transform_node("Helper", 5.0),
+            transform_node("Hip", 0.0),
+        ])],
+    );
+    assert!(matches!(
+        validate(&reference, &modified),
+        SafetyRating::Safe
+    ));
+}
+
+#[test]
+fn visibility_changes_are_safe() {
+    let reference = anim_data(
+        1,
+        vec![
+            transform_group(vec![transform_node("Hip", 0.0)]),
+            group(GroupType::Visibility, vec![visibility_node("EyeL", true)]),
+        ],
+    );
+    let modified = anim_data(
+        1,
+        vec![
+            transform_group(vec![transform_node("Hip", 0.0)]),
+            group(GroupType::Visibility, vec![visibility_node("EyeL", false)]),
+        ],
+    );
+    assert!(matches!(
+        validate(&reference, &modified),
+        SafetyRating::Safe
+    ));
+}
+
+#[test]
+fn changed_vanilla_bone_is_unsafe() {
+    let reference = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let modified = anim_data(1, vec![transform_group(vec![transform_node("Hip", 1.0)])]);
+    assert!(matches!(
+        validate(&reference, &modified),
+        SafetyRating::Unsafe(_)
+    ));
+}
+
+#[test]
+fn missing_vanilla_bone_is_unsafe() {
+    let reference = anim_data(
+        1,
+        vec![transform_group(vec![
+            transform_node("Hip", 0.0),
+            transform_node("Waist", 1.0),
+        ])],
+    );
+    let modified = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    assert!(matches!(
+        validate(&reference, &modified),
+        SafetyRating::Unsafe(_)
+    ));
+}
+
+#[test]
+fn missing_transform_track_is_unsafe() {
+    let reference = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let modified = anim_data(1, vec![transform_group(vec![node("Hip", Vec::new())])]);
+    assert!(matches!(
+        validate(&reference, &modified),
+        SafetyRating::Unsafe(_)
+    ));
+}
+
+#[test]
+fn missing_transform_group_is_unsafe() {
+    let reference = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let modified = anim_data(1, Vec::new());
+    assert!(matches!(
+        validate(&reference, &modified),
+        SafetyRating::Unsafe(_)
+    ));
+}
+
+#[test]
+fn different_final_frame_index_is_unsafe() {
+    let reference = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let mut modified = reference.clone();
+    modified.final_frame_index += 1.0;
+    assert!(matches!(
+        validate(&reference, &modified),
+        SafetyRating::Unsafe(_)
+    ));
+}
+
+#[test]
+fn added_transform_group_is_a_warning() {
+    let reference = anim_data(1, Vec::new());
+    let modified = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    assert!(matches!(
+        validate(&reference, &modified),
+        SafetyRating::Warning(_)
+    ));
+}
+
+#[test]
+fn unreadable_anim_is_a_warning() {
+    let anim = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let dir = TestDir::new();
+    let reference_path = dir.write_anim("reference.nuanmb", &anim);
+    let modified_path = dir.path.join("modified.nuanmb");
+    fs::write(&modified_path, b"not an anim").unwrap();
+
+    let rating = validate_anim(&reference_path, &modified_path, None);
+    assert!(matches!(rating, SafetyRating::Warning(_)));
+}
+
+/// Writes a manifest of the reference anim and validates the modified anim against the manifest alone.
+fn validate_with_manifest(
+    reference: &AnimData,
+    modified: &AnimData,
+    world_space_check: Option<&WorldSpaceCheck>,
+) -> SafetyRating {
+    let reference_dir = TestDir::new();
+    reference_dir.write_anim("a00wait1.nuanmb", reference);
+    let dir = TestDir::new();
+    let modified_path = dir.write_anim("a00wait1.nuanmb", modified);
+
+    let manifest_path = dir.path.join("manifest.json");
+    ReferenceManifest::from_dir(&reference_dir.path)
+        .unwrap()
+        .write_to_file(&manifest_path)
+        .unwrap();
+    let manifest = ReferenceManifest::from_file(&manifest_path).unwrap();
+
+    validate_anim_against_manifest(
+        manifest.get("a00wait1.nuanmb").unwrap(),
+        &modified_path,
+        world_space_check,
+    )
+}
+
+#[test]
+fn identical_anims_are_safe_against_manifest() {
+    let anim = anim_data(
+        1,
+        vec![transform_group(vec![
+            transform_node("Hip", 0.0),
+            transform_node("Waist", 1.0),
+        ])],
+    );
+    assert!(matches!(
+        validate_with_manifest(&anim, &anim, None),
+        SafetyRating::Safe
+    ));
+}
+
+#[test]
+fn added_bones_are_safe_against_manifest() {
+    let reference = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let modified = anim_data(
+        1,
+        vec![transform_group(vec![
+            transform_node("Helper", 5.0),
+            transform_node("Hip", 0.0),
+        ])],
+    );
+    assert!(matches!(
+        validate_with_manifest(&reference, &modified, None),
+        SafetyRating::Safe
+    ));
+}
+
+#[test]
+fn changed_vanilla_bone_is_unsafe_against_manifest() {
+    let reference = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let modified = anim_data(1, vec![transform_group(vec![transform_node("Hip", 1.0)])]);
+    assert!(matches!(
+        validate_with_manifest(&reference, &modified, None),
+        SafetyRating::Unsafe(_)
+    ));
+}
+
+#[test]
+fn missing_vanilla_bone_is_unsafe_against_manifest() {
+    let reference = anim_data(
+        1,
+        vec![transform_group(vec![
+            transform_node("Hip", 0.0),
+            transform_node("Waist", 1.0),
+        ])],
+    );
+    let modified = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    assert!(matches!(
+        validate_with_manifest(&reference, &modified, None),
+        SafetyRating::Unsafe(_)
+    ));
+}
+
+/// `Trans` is the root, with `Hip` and then `ArmL` below it, and `Sword` next to `Hip`.
+fn world_space_check(protected_bones: &[&str]) -> WorldSpaceCheck {
+    let bone = |name: &str, parent_index| BoneData {
+        name: name.to_string(),
+        transform: [[0.0; 4]; 4],
+        parent_index,
+        billboard_type: BillboardType::Disabled,
+    };
+    WorldSpaceCheck {
+        skeleton: SkelData {
+            major_version: 1,
+            minor_version: 0,
+            bones: vec![
+                bone("Trans", None),
+                bone("Hip", Some(0)),
+                bone("ArmL", Some(1)),
+                bone("Sword", Some(0)),
+            ],
+        },
+        protected_bones: protected_bones
+            .iter()
+            .map(|bone| bone.to_string())
+            .collect(),
+        hitbox_bones: None,
+        fighter: None,
+    }
+}
+
+/// Validates the modified anim in world space, against the reference anim and against its manifest.
+fn validate_in_world_space(
+    reference: &AnimData,
+    modified: &AnimData,
+    protected_bones: &[&str],
+) -> [SafetyRating; 2] {
+    let check = world_space_check(protected_bones);
+    let dir = TestDir::new();
+    let reference_path = dir.write_anim("reference.nuanmb", reference);
+    let modified_path = dir.write_anim("modified.nuanmb", modified);
+    [
+        validate_anim(&reference_path, &modified_path, Some(&check)),
+        validate_with_manifest(reference, modified, Some(&check)),
+    ]
+}
+
+/// The vanilla anim only animates `ArmL`, so its ancestors can be added without changing any vanilla track.
+fn arm_anim(added_bones: &[&str]) -> AnimData {
+    let mut nodes = vec![transform_node("ArmL", 0.0)];
+    nodes.extend(added_bones.iter().map(|name| transform_node(name, 1.0)));
+    anim_data(1, vec![transform_group(nodes)])
+}
+
+#[test]
+fn changed_ancestor_of_protected_bone_is_unsafe() {
+    // ArmL's own track is untouched, but moving Hip moves it in world space.
+    let modified = arm_anim(&["Hip"]);
+    assert!(matches!(
+        validate(&arm_anim(&[]), &modified),
+        SafetyRating::Safe
+    ));
+    for rating in validate_in_world_space(&arm_anim(&[]), &modified, &["ArmL"]) {
+        assert!(matches!(rating, SafetyRating::Unsafe(_)));
+    }
+}
+
+#[test]
+fn changed_unrelated_bone_is_safe_in_world_space() {
+    for rating in validate_in_world_space(&arm_anim(&[]), &arm_anim(&["Sword"]), &["ArmL"]) {
+        assert!(matches!(rating, SafetyRating::Safe));
+    }
+}
+
+#[test]
+fn every_vanilla_bone_is_protected_by_default() {
+    for rating in validate_in_world_space(&arm_anim(&[]), &arm_anim(&["Hip"]), &[]) {
+        assert!(matches!(rating, SafetyRating::Unsafe(_)));
+    }
+    for rating in validate_in_world_space(&arm_anim(&[]), &arm_anim(&["Sword"]), &[]) {
+        assert!(matches!(rating, SafetyRating::Safe));
+    }
+}
+
+#[test]
+fn certificate_with_a_wrong_checksum_is_refused() {
+    let dir = TestDir::new();
+    let certificate_path = dir.path.join("certificate.json");
+    Certificate::new(&Reference::from_path(&dir.path).unwrap(), &[], None)
+        .unwrap()
+        .write_to_file(&certificate_path)
+        .unwrap();
+    assert!(Certificate::from_file(&certificate_path).is_ok());
+
+    let json = fs::read_to_string(&certificate_path).unwrap();
+    fs::write(
+        &certificate_path,
+        json.replace(env!("CARGO_PKG_VERSION"), "0.0.0"),
+    )
+    .unwrap();
+    assert!(Certificate::from_file(&certificate_path).is_err());
+}
+
+/// Certifies `a00wait1.nuanmb` of a mod folder, then verifies the mod folder after `edit` ran on it.
+fn verify_after(edit: impl FnOnce(&TestDir)) -> anyhow::Result<()> {
+    let anim = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let reference_dir = TestDir::new();
+    reference_dir.write_anim("a00wait1.nuanmb", &anim);
+    let modified_dir = TestDir::new();
+    let modified_path = modified_dir.write_anim("a00wait1.nuanmb", &anim);
+
+    let certificate = Certificate::new(
+        &Reference::from_path(&reference_dir.path).unwrap(),
+        &[modified_path],
+        None,
+    )
+    .unwrap();
+    edit(&modified_dir);
+    verify_certificate(&certificate, &modified_dir.path)
+}
+
+#[test]
+fn unchanged_mod_is_verified() {
+    assert!(verify_after(|_| ()).is_ok());
+}
+
+#[test]
+fn changed_anim_with_matching_bones_is_verified() {
+    let modified = anim_data(
+        1,
+        vec![transform_group(vec![
+            transform_node("Helper", 5.0),
+            transform_node("Hip", 0.0),
+        ])],
+    );
+    assert!(verify_after(|dir| {
+        dir.write_anim("a00wait1.nuanmb", &modified);
+    })
+    .is_ok());
+}
+
+#[test]
+fn changed_protected_bone_fails_verification() {
+    let modified = anim_data(1, vec![transform_group(vec![transform_node("Hip", 1.0)])]);
+    assert!(verify_after(|dir| {
+        dir.write_anim("a00wait1.nuanmb", &modified);
+    })
+    .is_err());
+}
+
+#[test]
+fn changed_final_frame_index_fails_verification() {
+    let mut modified = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    modified.final_frame_index += 1.0;
+    assert!(verify_after(|dir| {
+        dir.write_anim("a00wait1.nuanmb", &modified);
+    })
+    .is_err());
+}
+
+#[test]
+fn slots_of_a_zip_mod_are_certified_separately() {
+    let anim = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let changed = anim_data(1, vec![transform_group(vec![transform_node("Hip", 1.0)])]);
+    let reference_dir = TestDir::new();
+    reference_dir.write_anim("a00wait1.nuanmb", &anim);
+    let modified_dir = TestDir::new();
+    let slots = ["c00/a00wait1.nuanmb", "c01/a00wait1.nuanmb"];
+    let zip_path = modified_dir.write_zip("mod.zip", &slots.map(|slot| (slot, &anim)));
+
+    let certificate = Certificate::new(
+        &Reference::from_path(&reference_dir.path).unwrap(),
+        &slots.map(|slot| zip_path.join(slot)),
+        None,
+    )
+    .unwrap();
+    assert_eq!(certificate.anim_count(), 2);
+    assert!(verify_certificate(&certificate, &zip_path).is_ok());
+
+    modified_dir.write_zip("mod.zip", &[(slots[0], &anim), (slots[1], &changed)]);
+    assert!(verify_certificate(&certificate, &zip_path).is_err());
+}
+
+#[test]
+fn missing_anim_fails_verification() {
+    assert!(
+        verify_after(|dir| fs::remove_file(dir.path.join("a00wait1.nuanmb")).unwrap()).is_err()
+    );
+}
+
+#[test]
+fn uncertified_anim_fails_verification() {
+    let anim = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    assert!(verify_after(|dir| {
+        dir.write_anim("a01wait2.nuanmb", &anim);
+    })
+    .is_err());
+}
+
+#[test]
+fn versions_are_rated_separately() {
+    let dir = TestDir::new();
+    let old = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let new = anim_data(1, vec![transform_group(vec![transform_node("Hip", 1.0)])]);
+    for (version, anim) in [("13.0.1", &old), ("13.0.2", &new)] {
+        fs::create_dir_all(dir.path.join(version)).unwrap();
+        dir.write_anim(&format!("{version}/a00wait1.nuanmb"), anim);
+    }
+    fs::create_dir_all(dir.path.join("13.0.3")).unwrap();
+    let modified_path = dir.write_anim("a00wait1.nuanmb", &old);
+
+    let versions = ["13.0.1", "13.0.2", "13.0.3"].map(|version| {
+        ReferenceVersion::from_arg(
+            &format!("{version}={}", dir.path.join(version).display())
+                .parse()
+                .unwrap(),
+        )
+        .unwrap()
+    });
+    let ratings = rate_versions(&versions, &modified_path, None);
+
+    assert!(matches!(ratings[0], ("13.0.1", Some(SafetyRating::Safe))));
+    assert!(matches!(
+        ratings[1],
+        ("13.0.2", Some(SafetyRating::Unsafe(_)))
+    ));
+    assert!(matches!(ratings[2], ("13.0.3", None)));
+}
+
+#[test]
+fn reference_version_needs_a_label_and_a_folder() {
+    assert!("13.0.1=vanilla".parse::<ReferenceVersionArg>().is_ok());
+    assert!("vanilla".parse::<ReferenceVersionArg>().is_err());
+    assert!("=vanilla".parse::<ReferenceVersionArg>().is_err());
+    assert!("13.0.1=".parse::<ReferenceVersionArg>().is_err());
+}
+
+#[test]
+fn manifest_is_not_a_reference_folder() {
+    let dir = TestDir::new();
+    let manifest_path = dir.path.join("manifest.json");
+    ReferenceManifest::from_dir(&dir.path)
+        .unwrap()
+        .write_to_file(&manifest_path)
+        .unwrap();
+    assert!(Reference::from_path(&manifest_path).is_err());
+
+    let version = format!("13.0.1={}", manifest_path.display())
+        .parse()
+        .unwrap();
+    assert!(matches!(
+        ReferenceVersion::from_arg(&version).unwrap().reference,
+        Reference::Manifest(_)
+    ));
+}
+
+#[test]
+fn duplicate_or_modified_reference_versions_are_refused() {
+    let reference_dir = TestDir::new();
+    let modified_dir = TestDir::new();
+    let version = |label: &str, dir: &TestDir| {
+        ReferenceVersion::from_arg(&format!("{label}={}", dir.path.display()).parse().unwrap())
+            .unwrap()
+    };
+
+    assert!(validate_versions(
+        &[
+            version("13.0.1", &reference_dir),
+            version("13.0.2", &reference_dir)
+        ],
+        &modified_dir.path,
+        None
+    )
+    .is_ok());
+    assert!(validate_versions(
+        &[
+            version("13.0.1", &reference_dir),
+            version("13.0.1", &reference_dir)
+        ],
+        &modified_dir.path,
+        None
+    )
+    .is_err());
+    assert!(validate_versions(
+        &[
+            version("13.0.1", &reference_dir),
+            version("13.0.2", &modified_dir)
+        ],
+        &modified_dir.path,
+        None
+    )
+    .is_err());
+}
+
+#[test]
+fn reference_folder_is_not_the_modified_folder() {
+    let dir = TestDir::new();
+    fs::create_dir_all(dir.path.join("a")).unwrap();
+    let reference = Reference::from_path(&dir.path.join("a")).unwrap();
+    let modified_dir = dir.path.join("a").join("..").join("a");
+    assert!(validate_dirs(&reference, &modified_dir, None).is_err());
+
+    fs::create_dir_all(dir.path.join("b")).unwrap();
+    assert!(validate_dirs(&reference, &dir.path.join("b"), None).is_ok());
+}
+
+#[test]
+fn non_anim_zip_entries_are_skipped() {
+    let anim = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let dir = TestDir::new();
+    let zip_path = dir.write_zip(
+        "mod.zip",
+        &[
+            ("fighter/mario/motion/body/c00/a00wait1.nuanmb", &anim),
+            ("fighter/mario/motion/body/c00/a00wait1.json", &anim),
+        ],
+    );
+    write_zip(
+        &dir.path.join("readme.zip"),
+        &[("readme.txt".to_string(), b"text".to_vec())],
+    )
+    .unwrap();
+
+    assert_eq!(
+        read_anim_dir(&zip_path, is_nuanmb_path).unwrap(),
+        vec![zip_path.join("fighter/mario/motion/body/c00/a00wait1.nuanmb")]
+    );
+    assert!(read_anim_dir(&dir.path.join("readme.zip"), is_nuanmb_path)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn non_anim_folder_entries_are_skipped() {
+    let anim = anim_data(1, vec![transform_group(vec![transform_node("Hip", 0.0)])]);
+    let dir = TestDir::new();
+    let anim_path = dir.write_anim("a00wait1.nuanmb", &anim);
+    dir.write_json("a00wait2.json", &anim);
+    fs::write(dir.path.join("readme.txt"), "text").unwrap();
+    fs::create_dir_all(dir.path.join("c00.nuanmb")).unwrap();
+
+    assert_eq!(
+        read_anim_dir(&dir.path, is_nuanmb_path).unwrap(),
+        vec![anim_path]
+    );
+}
+
+#[test]
+fn missing_anim_folder_is_an_error() {
+    let dir = TestDir::new();
+    assert!(read_anim_dir(&dir.path.join("missing"), is_nuanmb_path).is_err());
+    assert!(read_anim_dir(&dir.path.join("missing.zip"), is_nuanmb_path).is_err());
+}
@@ -0,0 +1,167 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::manifest::ReferenceManifest;
+use crate::{is_victory_anim, Reference};
+use wifisafe_common::archive::{is_json_path, is_nuanmb_path, is_same_path, read_anim_dir};
+use wifisafe_common::validation::{SafetyRating, WorldSpaceCheck};
+
+/// A `--reference_version` like `13.0.1=vanilla_13_0_1`.
+#[derive(Clone)]
+pub struct ReferenceVersionArg {
+    label: String,
+    path: PathBuf,
+}
+
+impl FromStr for ReferenceVersionArg {
+    type Err = String;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let (label, path) = arg.split_once('=').ok_or_else(|| {
+            format!("the reference version `{arg}` should look like `<version>=<folder>`")
+        })?;
+        if label.trim().is_empty() || path.trim().is_empty() {
+            return Err(format!(
+                "the reference version `{arg}` is missing a version or a folder"
+            ));
+        }
+        Ok(Self {
+            label: label.trim().to_string(),
+            path: PathBuf::from(path.trim()),
+        })
+    }
+}
+
+/// The vanilla anims of one version of the game.
+pub struct ReferenceVersion {
+    pub label: String,
+    pub reference: Reference,
+}
+
+impl ReferenceVersion {
+    /// Reads the version's reference folder or zip, or its manifest if the path ends in `.json`.
+    pub fn from_arg(arg: &ReferenceVersionArg) -> Result<Self> {
+        let reference = if is_json_path(&arg.path) {
+            ReferenceManifest::from_file(&arg.path).map(Reference::Manifest)
+        } else {
+            Reference::from_path(&arg.path)
+        };
+        Ok(Self {
+            label: arg.label.clone(),
+            reference: reference.with_context(|| {
+                format!("could not read the reference for version `{}`", arg.label)
+            })?,
+        })
+    }
+}
+
+/// Validates the modified anim against every version. `None` means the version has no vanilla anim with that name.
+pub fn rate_versions<'a>(
+    versions: &'a [ReferenceVersion],
+    modified_anim_path: &Path,
+    world_space_check: Option<&WorldSpaceCheck>,
+) -> Vec<(&'a str, Option<SafetyRating>)> {
+    versions
+        .iter()
+        .map(|version| {
+            (
+                version.label.as_str(),
+                version
+                    .reference
+                    .validate(modified_anim_path, world_space_check),
+            )
+        })
+        .collect()
+}
+
+/// Like [crate::validate_dirs], but reports which game versions each modified anim is safe against.
+pub fn validate_versions(
+    versions: &[ReferenceVersion],
+    modified_dir: &Path,
+    world_space_check: Option<&WorldSpaceCheck>,
+) -> Result<()> {
+    for (index, version) in versions.iter().enumerate() {
+        if versions[..index]
+            .iter()
+            .any(|other| other.label == version.label)
+        {
+            return Err(anyhow::format_err!(
+                "The reference version `{}` is given more than once!",
+                version.label
+            ));
+        }
+        if matches!(&version.reference, Reference::Folder { dir, .. } if is_same_path(dir, modified_dir))
+        {
+            return Err(anyhow::format_err!(
+                "The reference of version `{}` is the modified folder!",
+                version.label
+            ));
+        }
+    }
+
+    let modified_anim_paths = read_anim_dir(modified_dir, is_nuanmb_path)
+        .context("could not read the modified folder")?;
+
+    let mut safe_counts = vec![0; versions.len()];
+    let mut safe_for_every_version_count = 0;
+    let mut skip_count = 0;
+
+    for modified_anim_path in &modified_anim_paths {
+        let file_name = modified_anim_path.file_name().unwrap_or_default();
+        if is_victory_anim(&file_name.to_string_lossy()) {
+            println!("SKIPPED: Skipping {file_name:?}, since it's name starts with `j02` and is a victory screen animation.");
+            skip_count += 1;
+            continue;
+        }
+
+        let mut safe_versions = Vec::new();
+        let mut other_versions = Vec::new();
+        for (index, (label, rating)) in
+            rate_versions(versions, modified_anim_path, world_space_check)
+                .into_iter()
+                .enumerate()
+        {
+            match rating {
+                Some(SafetyRating::Safe) => {
+                    safe_counts[index] += 1;
+                    safe_versions.push(label);
+                }
+                Some(SafetyRating::Unsafe(msg)) => {
+                    println!("UNSAFE: Anim={file_name:?}, version=`{label}`, reason=`{msg}`");
+                    other_versions.push(label);
+                }
+                Some(SafetyRating::Warning(msg)) => {
+                    println!("WARNING: Anim={file_name:?}, version=`{label}`, reason=`{msg}`");
+                    other_versions.push(label);
+                }
+                None => {
+                    println!("WARNING: Anim={file_name:?}, version=`{label}`, reason=`no vanilla anim was found`");
+                    other_versions.push(label);
+                }
+            }
+        }
+
+        if other_versions.is_empty() {
+            safe_for_every_version_count += 1;
+        } else {
+            let safe_versions = if safe_versions.is_empty() {
+                "none".to_string()
+            } else {
+                safe_versions.join(", ")
+            };
+            println!(
+                "VERSIONS: Anim={file_name:?}, safe=`{safe_versions}`, not safe=`{}`",
+                other_versions.join(", ")
+            );
+        }
+    }
+
+    println!("Total Modified Anims: {}", modified_anim_paths.len());
+    for (version, safe_count) in versions.iter().zip(safe_counts) {
+        println!("Safe Count ({}): {safe_count}", version.label);
+    }
+    println!("Safe For Every Version Count: {safe_for_every_version_count}");
+    println!("Skip Count: {skip_count}");
+    Ok(())
+}